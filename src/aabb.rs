@@ -0,0 +1,163 @@
+use crate::interval::Interval;
+use crate::math::{Point3, Vec3};
+use crate::ray::Ray;
+
+/// Axis-aligned bounding box, stored as one interval per axis
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub x: Interval,
+    pub y: Interval,
+    pub z: Interval,
+}
+
+impl Aabb {
+    pub fn new(x: Interval, y: Interval, z: Interval) -> Self {
+        Self { x, y, z }
+    }
+
+    /// Box that contains nothing, neutral element of [`Aabb::union`]
+    pub fn empty() -> Self {
+        Self::new(Interval::empty(), Interval::empty(), Interval::empty())
+    }
+
+    /// Smallest box containing both points, in any order
+    pub fn from_points(a: Point3, b: Point3) -> Self {
+        Self::new(
+            Interval::new(a.x().min(b.x()), a.x().max(b.x())),
+            Interval::new(a.y().min(b.y()), a.y().max(b.y())),
+            Interval::new(a.z().min(b.z()), a.z().max(b.z())),
+        )
+    }
+
+    /// Smallest box containing both boxes
+    pub fn union(&self, other: &Aabb) -> Self {
+        Self::new(
+            self.x.union(&other.x),
+            self.y.union(&other.y),
+            self.z.union(&other.z),
+        )
+    }
+
+    /// Same box grown along the axes where it is thinner than `min_size`, so
+    /// that the slab test works for flat objects lying on an axis plane
+    pub fn pad(&self, min_size: f64) -> Self {
+        let pad = |i: &Interval| {
+            let delta = 0.5 * (min_size - i.size()).max(0.0);
+            Interval::new(i.min - delta, i.max + delta)
+        };
+        Self::new(pad(&self.x), pad(&self.y), pad(&self.z))
+    }
+
+    pub fn axis(&self, n: usize) -> &Interval {
+        match n {
+            1 => &self.y,
+            2 => &self.z,
+            _ => &self.x,
+        }
+    }
+
+    /// Index of the axis along which the box is the largest
+    pub fn longest_axis(&self) -> usize {
+        Vec3::new(self.x.size(), self.y.size(), self.z.size()).max_axis()
+    }
+
+    /// Slab test: checks if the ray crosses the box for some `t` in `bounds`
+    pub fn hit(&self, ray: &Ray, bounds: Interval) -> bool {
+        self.overlap(ray, bounds).is_some()
+    }
+
+    /// Parameters where the line of the ray enters and exits the box, the
+    /// entry is negative when the ray starts inside the box
+    pub fn hit_t(&self, ray: &Ray) -> Option<(f64, f64)> {
+        self.overlap(ray, Interval::universe())
+            .map(|span| (span.min, span.max))
+    }
+
+    /// Part of `bounds` where the ray is inside the box
+    fn overlap(&self, ray: &Ray, mut bounds: Interval) -> Option<Interval> {
+        let orig = ray.origin();
+        let dir = ray.direction();
+
+        for axis in 0..3 {
+            let ax = self.axis(axis);
+            let adinv = 1.0 / dir[axis];
+
+            let t0 = (ax.min - orig[axis]) * adinv;
+            let t1 = (ax.max - orig[axis]) * adinv;
+            let (t0, t1) = if t0 < t1 { (t0, t1) } else { (t1, t0) };
+
+            bounds.min = bounds.min.max(t0);
+            bounds.max = bounds.max.min(t1);
+            if bounds.max <= bounds.min {
+                return None;
+            }
+        }
+
+        Some(bounds)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.x.size() < 0.0 || self.y.size() < 0.0 || self.z.size() < 0.0
+    }
+
+    pub fn min(&self) -> Point3 {
+        Point3::new(self.x.min, self.y.min, self.z.min)
+    }
+
+    pub fn max(&self) -> Point3 {
+        Point3::new(self.x.max, self.y.max, self.z.max)
+    }
+
+    pub fn center(&self) -> Point3 {
+        0.5 * (self.min() + self.max())
+    }
+}
+
+#[cfg(test)]
+mod aabb_tests {
+    use super::*;
+    use crate::math::Vec3;
+
+    #[test]
+    fn slab_hit() {
+        let unit = Aabb::from_points(Point3::zero(), Point3::new(1., 1., 1.));
+        let through = Ray::new(Point3::new(0.5, 0.5, -1.), Vec3::new(0., 0., 1.));
+        let beside = Ray::new(Point3::new(2., 0.5, -1.), Vec3::new(0., 0., 1.));
+
+        assert!(unit.hit(&through, Interval::positive()));
+        assert!(!unit.hit(&beside, Interval::positive()));
+        assert!(!unit.hit(&through, Interval::new(0., 0.5)));
+    }
+
+    #[test]
+    fn entry_and_exit() {
+        let unit = Aabb::from_points(Point3::zero(), Point3::new(1., 1., 1.));
+
+        let through = Ray::new(Point3::new(0.5, 0.5, -1.), Vec3::new(0., 0., 1.));
+        assert_eq!(unit.hit_t(&through), Some((1.0, 2.0)));
+
+        let diagonal = Ray::new(Point3::new(-1., -1., -1.), Vec3::new(1., 1., 1.));
+        assert_eq!(unit.hit_t(&diagonal), Some((1.0, 2.0)));
+
+        let inside = Ray::new(Point3::new(0.5, 0.5, 0.25), Vec3::new(0., 0., 1.));
+        assert_eq!(unit.hit_t(&inside), Some((-0.25, 0.75)));
+        assert!(unit.hit(&inside, Interval::positive()));
+
+        let beside = Ray::new(Point3::new(2., 0.5, -1.), Vec3::new(0., 0., 1.));
+        assert_eq!(unit.hit_t(&beside), None);
+    }
+
+    #[test]
+    fn union() {
+        let a = Aabb::from_points(Point3::new(1., 1., 1.), Point3::new(0., 0., 0.));
+        let b = Aabb::from_points(Point3::new(-1., 0., 0.), Point3::new(0., 2., 0.5));
+
+        let u = a.union(&b);
+        assert_eq!(u.min(), Point3::new(-1., 0., 0.));
+        assert_eq!(u.max(), Point3::new(1., 2., 1.));
+
+        assert!(Aabb::empty().is_empty());
+        assert_eq!(u.longest_axis(), 1);
+        assert_eq!(Aabb::empty().union(&a), a);
+    }
+}
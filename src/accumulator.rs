@@ -0,0 +1,211 @@
+//! Running sums of the samples of every pixel, which can be saved to a
+//! checkpoint and loaded back to continue the render later
+
+use crate::camera::Pixel;
+use crate::image::{Color, Image};
+
+use std::io;
+
+const MAGIC: &[u8; 4] = b"RTCK";
+const VERSION: u32 = 2;
+
+/// Weighted sums of the samples taken so far for a pixel
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PixelSum {
+    pub color: Color,
+    pub coverage: f64,
+    pub weight: f64,
+    /// Number of samples taken
+    pub samples: u32,
+}
+
+impl PixelSum {
+    /// Averaged pixel, black if no sample has been taken
+    pub fn pixel(&self) -> Pixel {
+        if self.weight == 0.0 {
+            return Pixel::default();
+        }
+        Pixel {
+            color: self.color / self.weight,
+            alpha: self.coverage / self.weight,
+        }
+    }
+}
+
+/// Row-major sums of all the pixels of an image
+#[derive(Debug, Clone, PartialEq)]
+pub struct Accumulator {
+    pub image: Image,
+    /// Identifies the settings the sums were taken with, so that a
+    /// checkpoint isn't resumed with different ones, see
+    /// [`crate::camera::Camera::fingerprint`]. 0 when unknown.
+    pub fingerprint: u64,
+    pub sums: Vec<PixelSum>,
+}
+
+impl Accumulator {
+    pub fn new(image: Image) -> Self {
+        Self {
+            image,
+            fingerprint: 0,
+            sums: vec![PixelSum::default(); (image.width * image.height) as usize],
+        }
+    }
+
+    pub fn pixels(&self) -> Vec<Pixel> {
+        self.sums.iter().map(PixelSum::pixel).collect()
+    }
+
+    /// Smallest number of samples taken by a pixel
+    pub fn min_samples(&self) -> u32 {
+        self.sums.iter().map(|s| s.samples).min().unwrap_or(0)
+    }
+
+    /// Writes the sums in a little-endian binary format, floats are stored
+    /// exactly so a resumed render matches an uninterrupted one
+    pub fn save(&self, w: &mut impl io::Write) -> io::Result<()> {
+        w.write_all(MAGIC)?;
+        w.write_all(&VERSION.to_le_bytes())?;
+        w.write_all(&self.image.width.to_le_bytes())?;
+        w.write_all(&self.image.height.to_le_bytes())?;
+        w.write_all(&self.fingerprint.to_le_bytes())?;
+        for sum in &self.sums {
+            for v in [
+                sum.color.x(),
+                sum.color.y(),
+                sum.color.z(),
+                sum.coverage,
+                sum.weight,
+            ] {
+                w.write_all(&v.to_le_bytes())?;
+            }
+            w.write_all(&sum.samples.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub fn load(r: &mut impl io::Read) -> io::Result<Self> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+
+        let mut magic = [0; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("Not a render checkpoint"));
+        }
+        if read_u32(r)? != VERSION {
+            return Err(invalid("Unsupported checkpoint version"));
+        }
+
+        let width = read_u32(r)? as i32;
+        let height = read_u32(r)? as i32;
+        let mut fingerprint = [0; 8];
+        r.read_exact(&mut fingerprint)?;
+        let fingerprint = u64::from_le_bytes(fingerprint);
+        let image = Image::new(width, height).map_err(|e| invalid(&e))?;
+        let Some(count) = width.checked_mul(height) else {
+            return Err(invalid("Checkpoint image is too large"));
+        };
+
+        // grown while reading, a truncated file fails before allocating the
+        // sums of all the pixels of its header
+        let mut sums = Vec::new();
+        for _ in 0..count {
+            let mut v = [0.0; 5];
+            for x in v.iter_mut() {
+                *x = read_f64(r)?;
+            }
+            sums.push(PixelSum {
+                color: Color::new(v[0], v[1], v[2]),
+                coverage: v[3],
+                weight: v[4],
+                samples: read_u32(r)?,
+            });
+        }
+        if r.read(&mut [0])? != 0 {
+            return Err(invalid("Checkpoint has more sums than pixels"));
+        }
+        Ok(Self {
+            image,
+            fingerprint,
+            sums,
+        })
+    }
+}
+
+/// FNV-1a hash of `bytes`, stable across builds and platforms unlike the
+/// hashers of the standard library, so it can be stored in the checkpoints
+pub fn stable_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn read_u32(r: &mut impl io::Read) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_f64(r: &mut impl io::Read) -> io::Result<f64> {
+    let mut buf = [0; 8];
+    r.read_exact(&mut buf)?;
+    Ok(f64::from_le_bytes(buf))
+}
+
+#[cfg(test)]
+mod accumulator_tests {
+    use super::*;
+
+    #[test]
+    fn save_load() {
+        let mut acc = Accumulator::new(Image::new(3, 2).unwrap());
+        acc.fingerprint = 0x0123_4567_89ab_cdef;
+        acc.sums[4] = PixelSum {
+            color: Color::new(0.1, 0.2, 1.0 / 3.0),
+            coverage: 0.5,
+            weight: 2.0,
+            samples: 2,
+        };
+
+        let mut file = Vec::new();
+        acc.save(&mut file).unwrap();
+        let loaded = Accumulator::load(&mut file.as_slice()).unwrap();
+
+        assert_eq!(loaded, acc);
+        assert_eq!(loaded.min_samples(), 0);
+        assert_eq!(loaded.pixels()[4].alpha, 0.25);
+
+        assert!(Accumulator::load(&mut &file[..10]).is_err());
+        assert!(Accumulator::load(&mut b"P3\n1 1\n255\n".as_slice()).is_err());
+    }
+
+    #[test]
+    fn load_checks_sizes() {
+        let mut file = Vec::new();
+        Accumulator::new(Image::new(2, 2).unwrap())
+            .save(&mut file)
+            .unwrap();
+
+        // one sum missing, one too many
+        let sum_size = 5 * 8 + 4;
+        assert!(Accumulator::load(&mut &file[..file.len() - sum_size]).is_err());
+        let mut longer = file.clone();
+        longer.extend_from_slice(&file[file.len() - sum_size..]);
+        assert!(Accumulator::load(&mut longer.as_slice()).is_err());
+
+        // pixel count overflowing an i32
+        let mut huge = file[..8].to_vec();
+        huge.extend_from_slice(&65536u32.to_le_bytes());
+        huge.extend_from_slice(&65536u32.to_le_bytes());
+        huge.extend_from_slice(&0u64.to_le_bytes());
+        let err = Accumulator::load(&mut huge.as_slice()).unwrap_err();
+        assert!(err.to_string().contains("too large"), "{}", err);
+    }
+
+    #[test]
+    fn stable_hashes() {
+        assert_eq!(stable_hash(b""), 0xcbf29ce484222325);
+        assert_eq!(stable_hash(b"a"), 0xaf63dc4c8601ec8c);
+        assert_ne!(stable_hash(b"seed 1"), stable_hash(b"seed 2"));
+    }
+}
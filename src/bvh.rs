@@ -0,0 +1,183 @@
+//! Bounding volume hierarchy, speeds up the intersection of large scenes by
+//! skipping the objects whose bounding boxes are not crossed by the ray
+
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable, HittableList};
+use crate::interval::Interval;
+use crate::light::Light;
+use crate::ray::Ray;
+
+use std::sync::Arc;
+
+enum BvhChild {
+    Node(Box<BvhNode>),
+    Leaf(Box<dyn Hittable>),
+}
+
+pub struct BvhNode {
+    left: BvhChild,
+    right: Option<BvhChild>,
+    bbox: Aabb,
+}
+
+impl BvhNode {
+    /// Builds the hierarchy splitting the objects in half along the longest
+    /// axis of their bounding box, recursively
+    pub fn new(world: HittableList) -> Self {
+        let mut objects = world.into_objects();
+        if objects.is_empty() {
+            return Self {
+                left: BvhChild::Leaf(Box::new(HittableList::new())),
+                right: None,
+                bbox: Aabb::empty(),
+            };
+        }
+        Self::build(&mut objects)
+    }
+
+    fn build(objects: &mut Vec<Box<dyn Hittable>>) -> Self {
+        let bbox = objects
+            .iter()
+            .fold(Aabb::empty(), |bbox, obj| bbox.union(&obj.bounding_box()));
+
+        if objects.len() == 1 {
+            return Self {
+                left: BvhChild::Leaf(objects.pop().unwrap()),
+                right: None,
+                bbox,
+            };
+        }
+
+        let axis = bbox.longest_axis();
+        objects.sort_by(|a, b| {
+            let a = a.bounding_box().axis(axis).min;
+            let b = b.bounding_box().axis(axis).min;
+            a.total_cmp(&b)
+        });
+
+        let mut right = objects.split_off(objects.len() / 2);
+        let left = BvhNode::child(objects);
+        let right = BvhNode::child(&mut right);
+
+        Self {
+            left,
+            right: Some(right),
+            bbox,
+        }
+    }
+
+    fn child(objects: &mut Vec<Box<dyn Hittable>>) -> BvhChild {
+        if objects.len() == 1 {
+            BvhChild::Leaf(objects.pop().unwrap())
+        } else {
+            BvhChild::Node(Box::new(BvhNode::build(objects)))
+        }
+    }
+
+    /// Same as [`Hittable::hit`], also counting in `tests` the number of
+    /// box and primitive intersection tests performed
+    pub fn hit_counted(&self, ray: &Ray, bounds: Interval, tests: &mut u32) -> Option<HitRecord> {
+        *tests += 1;
+        if !self.bbox.hit(ray, bounds) {
+            return None;
+        }
+
+        let left = BvhNode::hit_child(&self.left, ray, bounds, tests);
+        let closest = left.as_ref().map_or(bounds.max, |rec| rec.t);
+        let right = self.right.as_ref().and_then(|right| {
+            BvhNode::hit_child(right, ray, Interval::new(bounds.min, closest), tests)
+        });
+
+        right.or(left)
+    }
+
+    fn hit_child(
+        child: &BvhChild,
+        ray: &Ray,
+        bounds: Interval,
+        tests: &mut u32,
+    ) -> Option<HitRecord> {
+        match child {
+            BvhChild::Node(node) => node.hit_counted(ray, bounds, tests),
+            BvhChild::Leaf(obj) => {
+                *tests += 1;
+                obj.hit(ray, bounds)
+            }
+        }
+    }
+
+    fn hit_child_any(child: &BvhChild, ray: &Ray, bounds: Interval) -> bool {
+        match child {
+            BvhChild::Node(node) => node.hit_any(ray, bounds),
+            BvhChild::Leaf(obj) => obj.hit_any(ray, bounds),
+        }
+    }
+}
+
+impl Hittable for BvhNode {
+    fn hit(&self, ray: &Ray, bounds: Interval) -> Option<HitRecord> {
+        self.hit_counted(ray, bounds, &mut 0)
+    }
+
+    fn hit_any(&self, ray: &Ray, bounds: Interval) -> bool {
+        self.bbox.hit(ray, bounds)
+            && (BvhNode::hit_child_any(&self.left, ray, bounds)
+                || self
+                    .right
+                    .as_ref()
+                    .is_some_and(|right| BvhNode::hit_child_any(right, ray, bounds)))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn lights(&self) -> Vec<Arc<dyn Light>> {
+        let child_lights = |child: &BvhChild| match child {
+            BvhChild::Node(node) => node.lights(),
+            BvhChild::Leaf(obj) => obj.lights(),
+        };
+        let mut lights = child_lights(&self.left);
+        if let Some(right) = &self.right {
+            lights.extend(child_lights(right));
+        }
+        lights
+    }
+
+    fn triangle_count(&self) -> usize {
+        let child_count = |child: &BvhChild| match child {
+            BvhChild::Node(node) => node.triangle_count(),
+            BvhChild::Leaf(obj) => obj.triangle_count(),
+        };
+        child_count(&self.left) + self.right.as_ref().map_or(0, child_count)
+    }
+}
+
+#[cfg(test)]
+mod bvh_tests {
+    use super::*;
+    use crate::math::{Point3, Vec3};
+    use crate::sphere::Sphere;
+
+    #[test]
+    fn same_hits_as_list() {
+        let mut list = HittableList::new();
+        let mut bvh_list = HittableList::new();
+        for i in 0..10 {
+            let x = i as f64 - 5.0;
+            list.add(Sphere::from_center_radius(x, 0., -3. - x, 0.4));
+            bvh_list.add(Sphere::from_center_radius(x, 0., -3. - x, 0.4));
+        }
+        let bvh = BvhNode::new(bvh_list);
+        assert_eq!(bvh.bounding_box(), list.bounding_box());
+
+        for i in 0..50 {
+            let dir = Vec3::new(i as f64 / 10. - 2.5, 0.01, -1.);
+            let ray = Ray::new(Point3::zero(), dir);
+            let expected = list.hit(&ray, Interval::positive()).map(|rec| rec.t);
+            let actual = bvh.hit(&ray, Interval::positive()).map(|rec| rec.t);
+            assert_eq!(expected, actual);
+            assert_eq!(expected.is_some(), bvh.hit_any(&ray, Interval::positive()));
+        }
+    }
+}
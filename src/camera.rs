@@ -1,11 +1,48 @@
-use crate::hittable::{Hittable, HittableList};
-use crate::image::{Color, Image};
+use crate::accumulator::{self, Accumulator, PixelSum};
+use crate::bvh::BvhNode;
+use crate::environment::CubeMap;
+use crate::filter::PixelFilter;
+use crate::hittable::{HitRecord, Hittable, HittableList};
+use crate::image::{Color, Image, luminance};
 use crate::interval::Interval;
-use crate::math::{Point3, Vec3, lerp};
-use crate::random::normal_random;
-use crate::ray::Ray;
+use crate::light::Light;
+use crate::material::{DiffuseMode, Material};
+use crate::math::{Point3, Vec3, lerp, power_heuristic};
+use crate::overlay;
+use crate::progress::{Progress, ProgressCallback};
+use crate::random::{self, normal_random};
+use crate::ray::{Ray, RayDifferentials};
+use crate::sampler::Sampler;
+use crate::stats::{self, RayCounts, RenderStats};
 
-use std::io;
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::f64::consts::PI;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Length in world units of the axes drawn by `draw_axes`
+const AXES_LENGTH: f64 = 1.0;
+
+/// Cosine of the smallest angle between the normals of neighbor pixels that
+/// is drawn as an edge by `outline`
+const OUTLINE_MIN_COSINE: f64 = 0.9;
+
+/// Distance from the light where the shadow rays stop, avoids the geometry
+/// of the lights shadowing themselves
+const SHADOW_EPSILON: f64 = 1e-3;
+
+thread_local! {
+    /// Pixel sampled by the current thread, reported by the `debug_check`
+    /// panics
+    static CURRENT_PIXEL: Cell<(i32, i32)> = const { Cell::new((0, 0)) };
+}
 
 /// Transform a component from linear to gamma using "gamma 2" transform
 pub fn linear_to_gamma(linear_component: f64) -> f64 {
@@ -15,54 +52,377 @@ pub fn linear_to_gamma(linear_component: f64) -> f64 {
     0.0
 }
 
+/// Transform applied to the linear color components before they are clamped
+/// and quantized by the image writers
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ToneMap {
+    /// "gamma 2" transform, see [`linear_to_gamma`]
+    #[default]
+    Gamma2,
+    /// Writes the linear values as they are
+    None,
+}
+
+impl ToneMap {
+    pub fn apply(&self, linear_component: f64) -> f64 {
+        match self {
+            ToneMap::Gamma2 => linear_to_gamma(linear_component),
+            ToneMap::None => linear_component,
+        }
+    }
+}
+
+/// How the colors brighter than white are brought in range before being
+/// written
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ClampMode {
+    /// Every channel is clamped on its own by the image writers
+    #[default]
+    PerChannel,
+    /// Channels are scaled down together, see [`Color::clamp_preserve_hue`]
+    PreserveHue,
+}
+
+impl ClampMode {
+    pub fn apply(&self, c: &Color) -> Color {
+        match self {
+            ClampMode::PerChannel => *c,
+            ClampMode::PreserveHue => c.clamp_preserve_hue(),
+        }
+    }
+}
+
+/// Gray level of the depths in [`Camera::render_depth`]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum DepthShading {
+    /// White at the near distance and black at the far one
+    #[default]
+    NearWhite,
+    /// Black at the near distance and white at the far one
+    NearBlack,
+}
+
 /// PPM extension functions
 pub mod ppm {
     use super::*;
 
-    pub fn header(w: &mut impl io::Write, img: &Image) -> io::Result<()> {
-        write!(w, "P3\n{} {}\n255\n", img.width, img.height)?;
-        Ok(())
-    }
-
-    pub fn write_color(w: &mut impl io::Write, c: &Color) -> io::Result<()> {
-        let r = linear_to_gamma(c.x());
-        let g = linear_to_gamma(c.y());
-        let b = linear_to_gamma(c.z());
+    /// Default maximum value of a channel, 8 bits per channel
+    pub const MAX_VALUE_8: u16 = 255;
+    /// Maximum value of a channel with 16 bits per channel
+    pub const MAX_VALUE_16: u16 = 65535;
 
-        let intensity = Interval::new(0.0, 0.999);
+    /// Whether the channels going up to `max_value` are written as binary
+    /// big-endian pairs of bytes (P6) rather than ASCII numbers (P3)
+    fn is_binary(max_value: u16) -> bool {
+        max_value > MAX_VALUE_8
+    }
 
-        let rbyte = 255.0 * intensity.clamp(r);
-        let gbyte = 255.0 * intensity.clamp(g);
-        let bbyte = 255.0 * intensity.clamp(b);
+    /// Writes the header of a PPM whose channels go up to `max_value`: ASCII
+    /// P3 for 8 bits, binary P6 with two bytes per channel above
+    pub fn header(w: &mut impl io::Write, img: &Image, max_value: u16) -> io::Result<()> {
+        let magic = if is_binary(max_value) { "P6" } else { "P3" };
+        write!(
+            w,
+            "{}\n{} {}\n{}\n",
+            magic, img.width, img.height, max_value
+        )?;
+        Ok(())
+    }
 
-        write!(w, "{} {} {} ", rbyte as i16, gbyte as i16, bbyte as i16,)?;
+    pub fn write_color(
+        w: &mut impl io::Write,
+        c: &Color,
+        tone_map: ToneMap,
+        max_value: u16,
+    ) -> io::Result<()> {
+        if is_binary(max_value) {
+            // the whole range, white is max_value rather than 0.999 of it
+            let max = max_value as f64;
+            for channel in [c.x(), c.y(), c.z()] {
+                let level = (max * tone_map.apply(channel).clamp(0.0, 1.0)).round() as u16;
+                w.write_all(&level.to_be_bytes())?;
+            }
+        } else {
+            let [r, g, b] = c.to_channels(tone_map, max_value);
+            write!(w, "{} {} {} ", r, g, b)?;
+        }
         Ok(())
     }
 
     pub fn new_line(w: &mut impl io::Write) -> io::Result<()> {
-        writeln!(w, "")?;
+        writeln!(w)?;
+        Ok(())
+    }
+
+    /// Writes a whole row-major buffer of `img.width * img.height` colors
+    pub fn write(
+        w: &mut impl io::Write,
+        img: &Image,
+        buffer: &[Color],
+        tone_map: ToneMap,
+        max_value: u16,
+    ) -> io::Result<()> {
+        header(w, img, max_value)?;
+        for row in buffer.chunks(img.width as usize) {
+            write_row(w, row, tone_map, max_value)?;
+        }
         Ok(())
     }
+
+    pub fn write_row(
+        w: &mut impl io::Write,
+        row: &[Color],
+        tone_map: ToneMap,
+        max_value: u16,
+    ) -> io::Result<()> {
+        for c in row {
+            write_color(w, c, tone_map, max_value)?;
+        }
+        // the binary samples follow each other without separators
+        if is_binary(max_value) {
+            return Ok(());
+        }
+        new_line(w)
+    }
+
+    /// Writes the rows of an image handed in any order: every row is written
+    /// as soon as all the rows above it are, only the rows finished ahead of
+    /// time are kept in memory
+    pub struct RowWriter<W: io::Write> {
+        w: W,
+        tone_map: ToneMap,
+        max_value: u16,
+        /// Index of the next row to be written
+        next_row: i32,
+        pending: BTreeMap<i32, Vec<Color>>,
+        /// Largest number of rows kept in memory at once
+        pub peak_pending: usize,
+    }
+
+    impl<W: io::Write> RowWriter<W> {
+        /// Writes the header of the image, then waits for the rows
+        pub fn new(mut w: W, img: &Image, tone_map: ToneMap, max_value: u16) -> io::Result<Self> {
+            header(&mut w, img, max_value)?;
+            Ok(Self {
+                w,
+                tone_map,
+                max_value,
+                next_row: 0,
+                pending: BTreeMap::new(),
+                peak_pending: 0,
+            })
+        }
+
+        /// Index of the first row not written yet
+        pub fn next_row(&self) -> i32 {
+            self.next_row
+        }
+
+        /// Adds the row `v`, writing it and the rows following it that are
+        /// already available if it is the next one
+        pub fn push(&mut self, v: i32, row: Vec<Color>) -> io::Result<()> {
+            self.pending.insert(v, row);
+            self.peak_pending = self.peak_pending.max(self.pending.len());
+            while let Some(row) = self.pending.remove(&self.next_row) {
+                write_row(&mut self.w, &row, self.tone_map, self.max_value)?;
+                self.next_row += 1;
+            }
+            Ok(())
+        }
+
+        /// Returns the writer once all the rows are written
+        pub fn finish(mut self) -> io::Result<W> {
+            self.w.flush()?;
+            Ok(self.w)
+        }
+    }
+}
+
+/// A rendered pixel
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Pixel {
+    pub color: Color,
+    /// Fraction of the primary rays of the pixel that hit some geometry, 0
+    /// where only the sky is visible
+    pub alpha: f64,
+}
+
+/// Extracts the colors of a buffer of pixels
+pub fn colors(pixels: &[Pixel]) -> Vec<Color> {
+    pixels.iter().map(|p| p.color).collect()
+}
+
+/// Paints with `color` the pixels of the buffer set in `mask`
+fn paint_mask(buffer: &mut [Color], mask: &[bool], color: &Color) {
+    for (c, _) in buffer.iter_mut().zip(mask).filter(|(_, set)| **set) {
+        *c = *color;
+    }
 }
+
 pub struct ViewportContext {
     pub delta_u: Vec3,
     pub delta_v: Vec3,
     pub upper_left_pixel: Point3,
 }
 
+/// Orthonormal frame of the camera: `u` points right, `v` up and `w`
+/// opposite to the view direction
+pub struct CameraBasis {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
 pub struct Camera {
     pub focal_length: f64,
+    /// Position of the camera (the "look from" point), origin of every ray
     pub center: Point3,
 
+    /// Point the camera is looking at
+    look_at: Point3,
+    /// Camera-relative "up" direction
+    vup: Vec3,
+
     pub viewport_height: f64,
     pub viewport_width: f64,
 
     pub image: Image,
 
-    /// Count of random samples for each pixel used for antialiasing
+    /// Transform applied to the rendered colors when they are written
+    pub tone_map: ToneMap,
+
+    /// Handling of the channels over 1 when the image is written
+    pub clamp_mode: ClampMode,
+
+    /// Factor applied to the linear colors of the samples as they are
+    /// accumulated, so every output gets it before the clamping and the tone
+    /// mapping
+    pub exposure: f64,
+
+    /// Gain of every channel applied along with the `exposure`, white keeps
+    /// the colors as they are
+    pub white_balance: Color,
+
+    /// Maximum channel value of the PPM output, [`ppm::MAX_VALUE_8`] or
+    /// [`ppm::MAX_VALUE_16`], the latter written as a binary P6
+    pub ppm_max_value: u16,
+
+    /// Count of random samples for each pixel used for antialiasing, values
+    /// below 1 render a single sample
     pub sample_per_pixel: i16,
 
+    /// Maximum number of bounces of a path, values below 1 are treated as 1
     pub max_recursion_depth: i16,
+
+    /// Firefly suppression: when set, every sample whose luminance is above
+    /// this value is scaled down to it before being averaged. This is
+    /// independent from the clamp applied when writing the image.
+    pub max_sample_luminance: Option<f64>,
+
+    /// Filter weighting the samples of each pixel
+    pub pixel_filter: PixelFilter,
+
+    /// Source of the sub-pixel offsets of the primary rays and of the
+    /// directions of the material bounces
+    pub sampler: Sampler,
+
+    /// Spreads the primary rays over the pixel for antialiasing. Without it
+    /// every sample goes through the pixel center, so that all the variance
+    /// of a pixel comes from the path tracing.
+    pub jitter: bool,
+
+    /// Sampling of the diffuse bounces, to compare the diffuse models
+    pub diffuse_mode: DiffuseMode,
+
+    /// Combines the direct sampling of the `lights` with the diffuse and
+    /// glossy bounces that hit them by multiple importance sampling (power
+    /// heuristic).
+    /// Without it the bounces ignore the emission of the sampled lights.
+    pub mis: bool,
+
+    /// Count of shadow rays toward each of the `lights` at every bounce,
+    /// averaged to reduce the noise of the soft shadows without tracing
+    /// more paths. Values below 1 trace a single one.
+    pub light_samples: u32,
+
+    /// Color and density of a distance fog: the color of the rays leaving
+    /// the camera is blended toward the fog color by
+    /// `1 - exp(-density * distance)` of their first hit. The sky is left as
+    /// it is.
+    pub fog: Option<(Color, f64)>,
+
+    /// Seen by the rays escaping the scene instead of the sky gradient of
+    /// [`Camera::background`]
+    pub environment: Option<Arc<CubeMap>>,
+
+    /// Hits closer than this to the origin of a ray are ignored, so that the
+    /// rays leaving a surface don't hit it again because of rounding errors
+    /// (shadow acne). Too large values let the light leak through the
+    /// surfaces close to each other.
+    pub min_hit_distance: f64,
+
+    /// Ends the paths randomly according to their throughput instead of
+    /// always tracing them to `max_recursion_depth`, see
+    /// [`Camera::rr_min_bounces`]
+    pub russian_roulette: bool,
+
+    /// Number of scatters a path always gets before the Russian roulette can
+    /// terminate it
+    pub rr_min_bounces: u32,
+
+    /// Radius of the lens aperture, 0 for a pinhole camera with everything in
+    /// focus. The focus plane is the viewport, at `focal_length` from the
+    /// center. The lens samples are averaged unclamped, so the blurred
+    /// highlights keep their energy and bloom over their neighbors unless
+    /// `max_sample_luminance` clamps them first.
+    pub defocus_radius: f64,
+
+    /// Number of blades of the aperture, which then is a regular polygon
+    /// shaping the out-of-focus highlights. Values below 3 give a circular
+    /// aperture.
+    pub aperture_blades: u32,
+
+    /// Color of the edges drawn where the surface normal changes sharply
+    /// between neighbor pixels, none by default
+    pub outline: Option<Color>,
+
+    /// Gray levels of [`Camera::render_depth`]
+    pub depth_shading: DepthShading,
+
+    /// Draws the world axes on top of the rendered image, for orientation
+    /// debugging
+    pub draw_axes: bool,
+
+    /// Writes the rows of the image from bottom to top
+    pub flip_vertical: bool,
+
+    /// Writes every row from right to left
+    pub flip_horizontal: bool,
+
+    /// Lights sampled explicitly at every hit, on top of the light coming
+    /// from the sky
+    pub lights: Vec<Arc<dyn Light>>,
+
+    /// Seed from which the random samples of every pixel are derived. Every
+    /// sample draws its numbers from a generator seeded with the seed, the
+    /// pixel coordinates and the sample index, so for a given seed the serial
+    /// and the parallel renders are byte-identical.
+    pub seed: u64,
+
+    /// Side in pixels of the square tiles handed out to the render threads
+    pub tile_size: i32,
+
+    /// Number of render threads, None to use all the available cores
+    pub threads: Option<usize>,
+
+    /// Called with the progress of the multithreaded renders, so that the
+    /// caller can show it. None by default: the renders report nothing.
+    pub progress: Option<ProgressCallback>,
+
+    /// Panics on the first ray direction, hit point or sample color that is
+    /// not finite, reporting the pixel being rendered. Meant to track down
+    /// the NaNs that otherwise end up as black pixels.
+    pub debug_check: bool,
 }
 
 impl Camera {
@@ -72,9 +432,41 @@ impl Camera {
             viewport_height: 0.0,
             viewport_width: 0.0,
             image: img,
+            tone_map: ToneMap::default(),
+            clamp_mode: ClampMode::default(),
+            exposure: 1.0,
+            white_balance: Color::new(1., 1., 1.),
+            ppm_max_value: ppm::MAX_VALUE_8,
             center: Point3::zero(),
+            look_at: Point3::new(0., 0., -1.),
+            vup: Vec3::new(0., 1., 0.),
             sample_per_pixel: 100,
             max_recursion_depth: 10,
+            max_sample_luminance: None,
+            pixel_filter: PixelFilter::default(),
+            sampler: Sampler::default(),
+            jitter: true,
+            diffuse_mode: DiffuseMode::default(),
+            mis: true,
+            light_samples: 1,
+            fog: None,
+            environment: None,
+            min_hit_distance: 1e-3,
+            russian_roulette: false,
+            rr_min_bounces: 3,
+            defocus_radius: 0.0,
+            aperture_blades: 0,
+            outline: None,
+            depth_shading: DepthShading::default(),
+            draw_axes: false,
+            flip_vertical: false,
+            flip_horizontal: false,
+            lights: Vec::new(),
+            seed: 0,
+            tile_size: 16,
+            threads: None,
+            progress: None,
+            debug_check: false,
         }
     }
 
@@ -83,12 +475,84 @@ impl Camera {
         self.viewport_width = viewport_height * self.image.ideal_ratio();
     }
 
+    /// Places the camera in `look_from`, looking at `look_at` with `vup` as
+    /// the up direction.
+    /// Nothing is cached: the basis and the viewport are derived from these
+    /// values every time they are needed, so they are never stale.
+    pub fn look_at(&mut self, look_from: Point3, look_at: Point3, vup: Vec3) {
+        self.center = look_from;
+        self.look_at = look_at;
+        self.vup = vup;
+    }
+
+    pub fn look_from(&self) -> Point3 {
+        self.center
+    }
+
+    pub fn set_look_from(&mut self, look_from: Point3) {
+        self.center = look_from;
+    }
+
+    /// Moves the camera to `center` keeping its orientation, the point it
+    /// looks at is translated by the same amount. Use
+    /// [`Camera::set_look_from`] to move the camera while keeping its target.
+    pub fn set_center(&mut self, center: Point3) {
+        let delta = center - self.center;
+        self.center = center;
+        self.look_at += delta;
+    }
+
+    pub fn target(&self) -> Point3 {
+        self.look_at
+    }
+
+    pub fn set_look_at(&mut self, look_at: Point3) {
+        self.look_at = look_at;
+    }
+
+    pub fn vup(&self) -> Vec3 {
+        self.vup
+    }
+
+    pub fn set_vup(&mut self, vup: Vec3) {
+        self.vup = vup;
+    }
+
+    /// Moves the camera along its current view direction so that the whole
+    /// bounding box of `world` fits in the viewport, looking at its center.
+    /// Does nothing if the world is empty.
+    pub fn frame_scene(&mut self, world: &HittableList) {
+        let bbox = world.bounding_box();
+        if bbox.is_empty() {
+            return;
+        }
+
+        let center = bbox.center();
+        let radius = 0.5 * bbox.max().distance(&bbox.min());
+
+        // Half of the narrowest field of view
+        let half_extent = 0.5 * self.viewport_height.min(self.viewport_width);
+        let half_fov = (half_extent / self.focal_length).atan();
+        let distance = radius / half_fov.sin();
+
+        let w = self.basis().w;
+        self.look_at = center;
+        self.center = center + distance * w;
+    }
+
+    pub fn basis(&self) -> CameraBasis {
+        let w = (self.center - self.look_at).normal();
+        let u = self.vup.cross(&w).normal();
+        let v = w.cross(&u);
+        CameraBasis { u, v, w }
+    }
+
     pub fn viewport_u(&self) -> Vec3 {
-        Vec3::new(self.viewport_width, 0., 0.)
+        self.viewport_width * self.basis().u
     }
 
     pub fn viewport_v(&self) -> Vec3 {
-        Vec3::new(0., -self.viewport_height, 0.)
+        -self.viewport_height * self.basis().v
     }
 
     pub fn delta_u(&self) -> Vec3 {
@@ -101,7 +565,7 @@ impl Camera {
 
     pub fn upper_left_viewport(&self) -> Vec3 {
         self.center
-            - Vec3::new(0., 0., self.focal_length)
+            - self.focal_length * self.basis().w
             - 0.5 * (self.viewport_u() + self.viewport_v())
     }
 
@@ -117,65 +581,2403 @@ impl Camera {
         }
     }
 
+    /// Number of threads used by the tiled renderer, see [`Camera::threads`]
+    pub fn worker_threads(&self) -> usize {
+        match self.threads {
+            Some(n) => n.max(1),
+            None => thread::available_parallelism().map_or(1, |n| n.get()),
+        }
+    }
+
+    /// Samples taken by every pixel, at least one even when
+    /// `sample_per_pixel` is not positive
+    fn samples(&self) -> u32 {
+        self.sample_per_pixel.max(1) as u32
+    }
+
+    /// Hash of the settings and the seed that change the accumulated samples,
+    /// stored in the checkpoints so that a render is only resumed by the same
+    /// camera. `sample_per_pixel` is left out, as resuming with more samples
+    /// is the point of the checkpoints, and so is the world: only the number
+    /// of `lights` and the presence of an `environment` are covered.
+    pub fn fingerprint(&self) -> u64 {
+        let settings = format!(
+            "{:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} \
+             {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?}",
+            self.focal_length,
+            self.center,
+            self.look_at,
+            self.vup,
+            self.viewport_height,
+            self.viewport_width,
+            self.image,
+            self.exposure,
+            self.white_balance,
+            self.max_recursion_depth,
+            self.max_sample_luminance,
+            self.pixel_filter,
+            self.sampler,
+            self.jitter,
+            self.diffuse_mode,
+            self.mis,
+            self.light_samples,
+            self.fog,
+            self.environment.is_some(),
+            self.min_hit_distance,
+            self.russian_roulette,
+            self.rr_min_bounces,
+            self.defocus_radius,
+            self.aperture_blades,
+            self.lights.len(),
+            self.seed,
+        );
+        accumulator::stable_hash(settings.as_bytes())
+    }
+
+    /// Checks that the camera can produce a meaningful image
+    pub fn validate(&self) -> Result<(), String> {
+        // written so that NaN values fail the checks too
+        let positive = |v: f64| v > 0.0;
+        if !positive(self.focal_length) {
+            return Err(format!(
+                "Focal length must be positive, got {}",
+                self.focal_length
+            ));
+        }
+        if !positive(self.viewport_width) || !positive(self.viewport_height) {
+            return Err(format!(
+                "Viewport must have a positive size, got {}x{}",
+                self.viewport_width, self.viewport_height
+            ));
+        }
+        if self.sample_per_pixel < 1 {
+            return Err(format!(
+                "At least one sample per pixel is needed, got {}",
+                self.sample_per_pixel
+            ));
+        }
+        Ok(())
+    }
+
     pub fn render(&self, target: &mut impl io::Write, world: &HittableList) -> io::Result<()> {
+        self.validate()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+        let buffer = self.render_output(world);
+        ppm::write(
+            target,
+            &self.image,
+            &buffer,
+            self.tone_map,
+            self.ppm_max_value,
+        )
+    }
+
+    /// Renders the image with the tiled renderer and brings the colors in
+    /// range with the `clamp_mode`, ready to be tone mapped and quantized
+    fn render_output(&self, world: &HittableList) -> Vec<Color> {
+        self.render_buffer_tiled(world)
+            .iter()
+            .map(|c| self.clamp_mode.apply(c))
+            .collect()
+    }
+
+    /// Linear color `c` with the `exposure` and the `white_balance` applied
+    fn grade(&self, c: &Color) -> Color {
+        self.exposure * self.white_balance * *c
+    }
+
+    /// Renders the image to an 8-bit [`image::RgbImage`], with the same tone
+    /// mapping and clamping as [`Camera::render`], so that it can be saved in
+    /// any format supported by the `image` crate
+    #[cfg(feature = "image")]
+    pub fn render_to_image_crate(&self, world: &HittableList) -> image::RgbImage {
+        let buffer = self.render_output(world);
+        let width = self.image.width as u32;
+        image::RgbImage::from_fn(width, self.image.height as u32, |x, y| {
+            image::Rgb(buffer[(y * width + x) as usize].to_rgb8(self.tone_map))
+        })
+    }
+
+    /// Same as [`Camera::render`], but every row is written to `target` as
+    /// soon as it and the rows above it are rendered, so that only a few rows
+    /// are in memory even for huge images. The render threads never get more
+    /// than two rows each ahead of the first unwritten row. The outline, the
+    /// axes and the vertical flip need the whole image, when any of them is
+    /// enabled this falls back to [`Camera::render`].
+    ///
+    /// Returns the largest number of rendered rows that waited at once for
+    /// the rows above them.
+    pub fn render_streaming(
+        &self,
+        target: &mut (impl io::Write + Send),
+        world: &HittableList,
+    ) -> io::Result<usize> {
+        if self.outline.is_some() || self.draw_axes || self.flip_vertical {
+            self.render(target, world)?;
+            return Ok(self.image.height as usize);
+        }
+        self.validate()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+        let viewport_ctx = self.viewport_context();
+        let height = self.image.height;
+        let workers = self.worker_threads().min(height as usize);
+        let window = 2 * workers as i32;
+
+        let writer = ppm::RowWriter::new(
+            BufWriter::new(target),
+            &self.image,
+            self.tone_map,
+            self.ppm_max_value,
+        )?;
+        let writer = Mutex::new(Ok(writer));
+        let row_written = Condvar::new();
+        let next_row = AtomicUsize::new(0);
+        let progress = Progress::new((self.image.width * height) as usize, self.progress.clone());
+
+        thread::scope(|s| {
+            for _ in 0..workers {
+                s.spawn(|| {
+                    loop {
+                        let v = next_row.fetch_add(1, Ordering::Relaxed) as i32;
+                        if v >= height {
+                            break;
+                        }
+
+                        // wait for the rows above to be written, so that the
+                        // rows kept in memory never exceed the window
+                        drop(
+                            row_written
+                                .wait_while(writer.lock().unwrap(), |w| {
+                                    w.as_ref().is_ok_and(|w| v >= w.next_row() + window)
+                                })
+                                .unwrap(),
+                        );
+
+                        let mut row: Vec<Color> = (0..self.image.width)
+                            .map(|u| {
+                                let pixel = self.render_pixel(u, v, &viewport_ctx, world);
+                                self.clamp_mode.apply(&pixel.color)
+                            })
+                            .collect();
+                        if self.flip_horizontal {
+                            row.reverse();
+                        }
+
+                        let mut w = writer.lock().unwrap();
+                        if let Ok(rows) = w.as_mut()
+                            && let Err(e) = rows.push(v, row)
+                        {
+                            *w = Err(e);
+                        }
+                        drop(w);
+                        row_written.notify_all();
+                        progress.add(self.image.width as usize);
+                    }
+                });
+            }
+        });
+        progress.finish();
+
+        let rows = writer.into_inner().unwrap()?;
+        let peak = rows.peak_pending;
+        rows.finish()?;
+        Ok(peak)
+    }
+
+    /// Same as [`Camera::render`] writing the PPM image to the file `path`
+    pub fn render_to_file(&self, world: &HittableList, path: &Path) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        self.render(&mut file, world)?;
+        file.flush()
+    }
+
+    /// Renders `frames` frames of an animation to `out_dir`, created if
+    /// missing, as `frame_0000.ppm`, `frame_0001.ppm` and so on. The world of
+    /// every frame is built by `build_scene` from the normalized time of the
+    /// frame, in `[0, 1)`. Returns the paths of the written files.
+    pub fn render_animation(
+        &self,
+        frames: u32,
+        build_scene: impl Fn(f64) -> HittableList,
+        out_dir: &Path,
+    ) -> io::Result<Vec<PathBuf>> {
+        fs::create_dir_all(out_dir)?;
+
+        let mut paths = Vec::with_capacity(frames as usize);
+        for frame in 0..frames {
+            let world = build_scene(frame as f64 / frames as f64);
+            let path = out_dir.join(format!("frame_{:04}.ppm", frame));
+            self.render_to_file(&world, &path)?;
+            paths.push(path);
+        }
+        Ok(paths)
+    }
+
+    /// Renders the image on the current thread, returning a row-major buffer
+    /// of linear colors. They are not clamped: channels can be over 1 or
+    /// negative, and are only brought in range when the image is written.
+    pub fn render_buffer(&self, world: &HittableList) -> Vec<Color> {
+        let outline = self.outline_mask(world);
+        self.post_process(&self.render_pixels(world), outline.as_deref())
+    }
+
+    /// Same as [`Camera::render_buffer`] but using [`Camera::render_pixels_tiled`]
+    pub fn render_buffer_tiled(&self, world: &HittableList) -> Vec<Color> {
+        let outline = self.outline_mask(world);
+        self.post_process(&self.render_pixels_tiled(world), outline.as_deref())
+    }
+
+    /// Turns the rendered pixels into the final colors, drawing the enabled
+    /// overlays and flipping the image if requested. `outline` is the
+    /// [`Camera::outline_mask`] of the render.
+    fn post_process(&self, pixels: &[Pixel], outline: Option<&[bool]>) -> Vec<Color> {
+        let mut buffer = colors(pixels);
+        if let (Some(color), Some(mask)) = (self.outline, outline) {
+            paint_mask(&mut buffer, mask, &color);
+        }
+        if self.draw_axes {
+            self.draw_axes_overlay(&mut buffer);
+        }
+
+        let width = self.image.width as usize;
+        if self.flip_vertical {
+            let rows: Vec<&[Color]> = buffer.chunks(width).rev().collect();
+            buffer = rows.concat();
+        }
+        if self.flip_horizontal {
+            buffer.chunks_mut(width).for_each(<[Color]>::reverse);
+        }
+        buffer
+    }
+
+    /// Paints with `color` the pixels where the normal of the surface seen by
+    /// a neighbor pixel differs sharply, silhouettes against the sky included
+    pub fn draw_outline(&self, buffer: &mut [Color], world: &HittableList, color: &Color) {
+        paint_mask(buffer, &self.outline_edges(world), color);
+    }
+
+    /// Pixels covered by the outline, None when it is disabled. It traces
+    /// the primary hits of the whole image, so the renders compute it once
+    /// and reuse it for every pass.
+    fn outline_mask(&self, world: &HittableList) -> Option<Vec<bool>> {
+        self.outline.map(|_| self.outline_edges(world))
+    }
+
+    /// Pixels where the normal of the surface seen by a neighbor pixel
+    /// differs sharply, see [`Camera::draw_outline`]
+    fn outline_edges(&self, world: &HittableList) -> Vec<bool> {
+        let normals = self.render_normals(world);
+        let (width, height) = (self.image.width as usize, self.image.height as usize);
+
+        let differs = |a: &Vec3, b: &Vec3| {
+            let (a_sky, b_sky) = (*a == Vec3::zero(), *b == Vec3::zero());
+            if a_sky || b_sky {
+                return a_sky != b_sky;
+            }
+            a.dot(b) < OUTLINE_MIN_COSINE
+        };
+
+        (0..height * width)
+            .map(|i| {
+                let (u, v) = (i % width, i / width);
+                let n = &normals[i];
+                let right = u + 1 < width && differs(n, &normals[i + 1]);
+                let below = v + 1 < height && differs(n, &normals[i + width]);
+                let left = u > 0 && differs(n, &normals[i - 1]);
+                let above = v > 0 && differs(n, &normals[i - width]);
+                right || below || left || above
+            })
+            .collect()
+    }
+
+    /// Draws the world axes from the origin: +X in red, +Y in green and +Z
+    /// in blue
+    pub fn draw_axes_overlay(&self, buffer: &mut [Color]) {
+        let axes = [
+            (Vec3::new(1., 0., 0.), Color::new(1., 0., 0.)),
+            (Vec3::new(0., 1., 0.), Color::new(0., 1., 0.)),
+            (Vec3::new(0., 0., 1.), Color::new(0., 0., 1.)),
+        ];
+
+        for (axis, color) in axes {
+            let Some((a, b)) = self.clip_to_front(Point3::zero(), AXES_LENGTH * axis) else {
+                continue;
+            };
+            if let (Some(a), Some(b)) = (self.project(&a), self.project(&b)) {
+                overlay::draw_line(buffer, &self.image, a, b, &color);
+            }
+        }
+    }
+
+    /// Continuous pixel coordinates of the world point `p`, the center of the
+    /// pixel `(u, v)` being at `(u, v)`. None if the point is behind the camera.
+    pub fn project(&self, p: &Point3) -> Option<(f64, f64)> {
+        let basis = self.basis();
+        let d = *p - self.center;
+        let depth = -d.dot(&basis.w);
+        if depth <= 0.0 {
+            return None;
+        }
+
+        // point on the viewport plane, relative to the center of the first pixel
+        let on_viewport = self.center + d * (self.focal_length / depth) - self.upper_left_pixel();
+        let delta_u = self.delta_u();
+        let delta_v = self.delta_v();
+        Some((
+            on_viewport.dot(&delta_u) / delta_u.length2(),
+            on_viewport.dot(&delta_v) / delta_v.length2(),
+        ))
+    }
+
+    /// Cuts the part of the segment between `a` and `b` that lies behind the
+    /// viewport plane, None if the whole segment is behind it
+    fn clip_to_front(&self, a: Point3, b: Point3) -> Option<(Point3, Point3)> {
+        let w = self.basis().w;
+        let near = self.focal_length;
+        let depth_a = -(a - self.center).dot(&w);
+        let depth_b = -(b - self.center).dot(&w);
+
+        match (depth_a >= near, depth_b >= near) {
+            (true, true) => Some((a, b)),
+            (false, false) => None,
+            _ => {
+                let t = (near - depth_a) / (depth_b - depth_a);
+                let cut = lerp(&a, &b, t);
+                if depth_a >= near {
+                    Some((a, cut))
+                } else {
+                    Some((cut, b))
+                }
+            }
+        }
+    }
+
+    /// Renders the image on the current thread, returning a row-major buffer
+    /// of pixels with their coverage
+    pub fn render_pixels(&self, world: &HittableList) -> Vec<Pixel> {
         let viewport_ctx = self.viewport_context();
 
-        ppm::header(target, &self.image)?;
+        let mut buffer = Vec::with_capacity((self.image.width * self.image.height) as usize);
         for v in 0..self.image.height {
-            eprint!("\rScanning lines [{}/{}]", v + 1, self.image.height);
             for u in 0..self.image.width {
-                let mut color = Color::zero();
-                for _ in 0..self.sample_per_pixel {
-                    let ray = self.get_ray(u, v, &viewport_ctx);
-                    color += Camera::ray_color(&ray, &world, self.max_recursion_depth);
-                }
-                color = color / self.sample_per_pixel as f64;
+                buffer.push(self.render_pixel(u, v, &viewport_ctx, world));
+            }
+        }
+
+        buffer
+    }
+
+    /// Renders the image splitting it in tiles of `tile_size` pixels. Every
+    /// worker thread claims the next free tile from a shared counter until
+    /// all the tiles are rendered, so fast tiles never leave a thread idle.
+    /// The result is the same as [`Camera::render_pixels`].
+    pub fn render_pixels_tiled(&self, world: &HittableList) -> Vec<Pixel> {
+        self.render_pixels_with_stats(world).0
+    }
+
+    /// Same as [`Camera::render_pixels_tiled`], also reporting the time taken
+    /// and the number of rays traced
+    pub fn render_pixels_with_stats(&self, world: &HittableList) -> (Vec<Pixel>, RenderStats) {
+        let mut acc = Accumulator::new(self.image);
+        let stats = self.accumulate(world, &mut acc, self.samples());
+        (acc.pixels(), stats)
+    }
+
+    /// Keeps sampling every pixel of `acc` until it has `target_samples`
+    /// samples, using the tiled renderer. As the samples only depend on the
+    /// seed and their index, continuing a render gives exactly the same
+    /// result as taking all the samples at once.
+    pub fn accumulate(
+        &self,
+        world: &HittableList,
+        acc: &mut Accumulator,
+        target_samples: u32,
+    ) -> RenderStats {
+        self.accumulate_rows(world, acc, target_samples, |_| true)
+    }
+
+    /// Same as [`Camera::accumulate`] restricted to the rows `v` for which
+    /// `rows(v)` is true, the other pixels are left untouched
+    fn accumulate_rows(
+        &self,
+        world: &HittableList,
+        acc: &mut Accumulator,
+        target_samples: u32,
+        rows: impl Fn(i32) -> bool + Sync,
+    ) -> RenderStats {
+        let start = Instant::now();
+        let viewport_ctx = self.viewport_context();
+
+        let tile_size = self.tile_size.max(1);
+        let tiles_u = (self.image.width + tile_size - 1) / tile_size;
+        let tiles_v = (self.image.height + tile_size - 1) / tile_size;
+        let tile_count = (tiles_u * tiles_v) as usize;
+
+        let workers = self.worker_threads();
+        let next_tile = AtomicUsize::new(0);
+        let rendered_rows = (0..self.image.height).filter(|&v| rows(v)).count();
+        let progress = Progress::new(
+            rendered_rows * self.image.width as usize,
+            self.progress.clone(),
+        );
+        let buffer = Mutex::new(std::mem::take(&mut acc.sums));
+        let counts = Mutex::new(RayCounts::zero());
+
+        thread::scope(|s| {
+            for _ in 0..workers.min(tile_count) {
+                s.spawn(|| {
+                    stats::take();
+                    loop {
+                        let tile = next_tile.fetch_add(1, Ordering::Relaxed);
+                        if tile >= tile_count {
+                            break;
+                        }
+
+                        let u0 = (tile as i32 % tiles_u) * tile_size;
+                        let v0 = (tile as i32 / tiles_u) * tile_size;
+                        let u1 = (u0 + tile_size).min(self.image.width);
+                        let v1 = (v0 + tile_size).min(self.image.height);
+                        let index = |u: i32, v: i32| (v * self.image.width + u) as usize;
+
+                        let mut pixels = Vec::with_capacity((tile_size * tile_size) as usize);
+                        {
+                            let buffer = buffer.lock().unwrap();
+                            for v in v0..v1 {
+                                for u in u0..u1 {
+                                    pixels.push(buffer[index(u, v)]);
+                                }
+                            }
+                        }
+
+                        let mut sums = pixels.iter_mut();
+                        let mut rendered = 0;
+                        for v in v0..v1 {
+                            for u in u0..u1 {
+                                let sum = sums.next().unwrap();
+                                if !rows(v) {
+                                    continue;
+                                }
+                                rendered += 1;
+                                self.accumulate_pixel(
+                                    u,
+                                    v,
+                                    &viewport_ctx,
+                                    world,
+                                    sum,
+                                    target_samples,
+                                );
+                            }
+                        }
+
+                        let mut buffer = buffer.lock().unwrap();
+                        let mut pixels = pixels.into_iter();
+                        for v in v0..v1 {
+                            for u in u0..u1 {
+                                buffer[index(u, v)] = pixels.next().unwrap();
+                            }
+                        }
+                        drop(buffer);
 
-                ppm::write_color(target, &color)?;
+                        progress.add(rendered);
+                    }
+                    counts.lock().unwrap().add(&stats::take());
+                });
             }
-            ppm::new_line(target)?;
+        });
+        progress.finish();
+
+        acc.sums = buffer.into_inner().unwrap();
+        RenderStats {
+            wall_time: start.elapsed(),
+            pixels: progress.done(),
+            counts: counts.into_inner().unwrap(),
         }
-        eprint!("\n");
+    }
 
-        Ok(())
+    /// Renders `sample_per_pixel` passes of one sample per pixel, calling
+    /// `on_frame` after every pass with the image averaged so far and the
+    /// number of passes done. The last frame is the same as
+    /// [`Camera::render_buffer`].
+    pub fn render_progressive(
+        &self,
+        world: &HittableList,
+        mut on_frame: impl FnMut(&[Color], u32),
+    ) {
+        let outline = self.outline_mask(world);
+        let mut acc = Accumulator::new(self.image);
+        for pass in 1..=self.samples() {
+            self.accumulate(world, &mut acc, pass);
+            on_frame(&self.post_process(&acc.pixels(), outline.as_deref()), pass);
+        }
     }
 
-    pub fn ray_color(ray: &Ray, world: &HittableList, depth: i16) -> Color {
-        if depth == 0 {
-            return Color::zero();
+    /// Interlaced render for quick previews of large images: the first pass
+    /// renders only the even rows and fills every odd row with a copy of the
+    /// row above, the second pass renders the odd rows. `on_pass` is called
+    /// after each pass with the image and the pass number, and the second
+    /// image is the same as [`Camera::render_buffer`].
+    pub fn render_interlaced(&self, world: &HittableList, mut on_pass: impl FnMut(&[Color], u32)) {
+        let target = self.samples();
+        let outline = self.outline_mask(world);
+        let mut acc = Accumulator::new(self.image);
+
+        self.accumulate_rows(world, &mut acc, target, |v| v % 2 == 0);
+        let width = self.image.width as usize;
+        let mut pixels = acc.pixels();
+        for v in (1..self.image.height as usize).step_by(2) {
+            pixels.copy_within((v - 1) * width..v * width, v * width);
         }
+        on_pass(&self.post_process(&pixels, outline.as_deref()), 1);
+
+        // the even rows already have all their samples
+        self.accumulate(world, &mut acc, target);
+        on_pass(&self.post_process(&acc.pixels(), outline.as_deref()), 2);
+    }
+
+    /// Takes one sample per pixel after the other until `budget` is elapsed,
+    /// returning the post-processed image, as [`Camera::render_buffer`], and
+    /// the number of samples taken by every pixel. At least one sample is
+    /// always taken, and `sample_per_pixel` is ignored.
+    pub fn render_timed(&self, world: &HittableList, budget: Duration) -> (Vec<Color>, u32) {
+        let start = Instant::now();
+        let outline = self.outline_mask(world);
+        let mut acc = Accumulator::new(self.image);
 
-        if let Some(rec) = world.hit(ray, Interval::positive()) {
-            let direction = rec.normal + Vec3::unit_random_on_sphere();
-            return 0.5 * Camera::ray_color(&Ray::new(rec.point, direction), world, depth - 1);
+        let mut samples = 0;
+        while samples == 0 || start.elapsed() < budget {
+            samples += 1;
+            self.accumulate(world, &mut acc, samples);
         }
 
-        let unit_direction = ray.direction().normal();
-        let blue = Color::new(0.5, 0.7, 1.0);
-        let white = Color::new(1.0, 1.0, 1.0);
+        (
+            self.post_process(&acc.pixels(), outline.as_deref()),
+            samples,
+        )
+    }
 
-        let t = 0.5 * (unit_direction.y() + 1.0);
+    /// Renders with periodic checkpoints: every `interval` samples per pixel
+    /// the accumulated sums are saved to `path`. If `path` already holds a
+    /// checkpoint of an image of the same size, the render resumes from it,
+    /// a checkpoint taken with other settings or another seed (see
+    /// [`Camera::fingerprint`]) is an error.
+    pub fn render_with_checkpoints(
+        &self,
+        world: &HittableList,
+        path: &Path,
+        interval: u32,
+    ) -> io::Result<Vec<Pixel>> {
+        let fingerprint = self.fingerprint();
+        let mut acc = if path.exists() {
+            Accumulator::load(&mut BufReader::new(File::open(path)?))?
+        } else {
+            Accumulator {
+                fingerprint,
+                ..Accumulator::new(self.image)
+            }
+        };
+        if acc.image.width != self.image.width || acc.image.height != self.image.height {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Checkpoint image size doesn't match the camera",
+            ));
+        }
+        if acc.fingerprint != fingerprint {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Checkpoint was rendered with other camera settings or seed",
+            ));
+        }
 
-        lerp(&white, &blue, t)
+        let target = self.samples();
+        let mut samples = acc.min_samples();
+        while samples < target {
+            samples = (samples + interval.max(1)).min(target);
+            self.accumulate(world, &mut acc, samples);
+
+            let mut file = BufWriter::new(File::create(path)?);
+            acc.save(&mut file)?;
+            file.flush()?;
+        }
+
+        Ok(acc.pixels())
     }
 
-    pub fn get_ray(&self, u: i32, v: i32, viewport_ctx: &ViewportContext) -> Ray {
-        let offset = Camera::sample_square();
+    /// Averages `sample_per_pixel` samples of the pixel `(u, v)`. The random
+    /// generator is reseeded from the pixel coordinates, so the result does
+    /// not depend on which thread renders the pixel or when.
+    pub fn render_pixel(
+        &self,
+        u: i32,
+        v: i32,
+        viewport_ctx: &ViewportContext,
+        world: &HittableList,
+    ) -> Pixel {
+        let mut sum = PixelSum::default();
+        let target = self.samples();
+        self.accumulate_pixel(u, v, viewport_ctx, world, &mut sum, target);
+        sum.pixel()
+    }
 
-        let pixel_sample = viewport_ctx.upper_left_pixel
-            + ((u as f64 + offset.x()) * viewport_ctx.delta_u
-                + (v as f64 + offset.y()) * viewport_ctx.delta_v);
+    /// Adds to `sum` the samples of the pixel `(u, v)` it is missing to reach
+    /// `target_samples`. Every sample reseeds the random generator from the
+    /// pixel coordinates and the sample index.
+    pub fn accumulate_pixel(
+        &self,
+        u: i32,
+        v: i32,
+        viewport_ctx: &ViewportContext,
+        world: &HittableList,
+        sum: &mut PixelSum,
+        target_samples: u32,
+    ) {
+        if self.debug_check {
+            CURRENT_PIXEL.with(|pixel| pixel.set((u, v)));
+        }
+        random::seed(random::pixel_seed(self.seed, u, v));
+        let rotation = self.sample_rotation();
+        let bounce_rotations = self.sampler.bounce_rotations();
 
-        let ray_origin = self.center;
-        let ray_dir = pixel_sample - self.center;
+        for i in sum.samples..target_samples {
+            random::seed(random::sample_seed(self.seed, u, v, i));
+            let _path = self.sampler.begin_path(i, &bounce_rotations);
 
-        let ray = Ray::new(ray_origin, ray_dir);
+            let offset = self.pixel_offset(i, rotation);
+            let weight = self.pixel_filter.weight(&offset);
 
-        ray
+            let ray = self.get_ray_at(u, v, &offset, viewport_ctx);
+            stats::count_primary_ray();
+            let (sample, rec) = self.trace(&ray, world, self.max_recursion_depth.max(1));
+            sum.color += weight * self.grade(&self.clamp_sample(sample));
+            self.check_finite("accumulated color", &sum.color);
+            if rec.is_some() {
+                sum.coverage += weight;
+            }
+            sum.weight += weight;
+        }
+        sum.samples = sum.samples.max(target_samples);
     }
 
-    /// Returns a random point in the square `[-0.5, 0.5] x [-0.5, 0.5] x {0}`
-    pub fn sample_square() -> Vec3 {
-        Vec3::new(normal_random() - 0.5, normal_random() - 0.5, 0.)
+    /// Synthetic test pattern that doesn't trace any ray: the red and green
+    /// channels are the normalized horizontal and vertical coordinates of the
+    /// pixel, from black at the top-left to yellow at the bottom-right
+    pub fn render_uv_test(&self) -> Vec<Color> {
+        let max_u = (self.image.width - 1).max(1) as f64;
+        let max_v = (self.image.height - 1).max(1) as f64;
+
+        let mut buffer = Vec::with_capacity((self.image.width * self.image.height) as usize);
+        for v in 0..self.image.height {
+            for u in 0..self.image.width {
+                buffer.push(Color::new(u as f64 / max_u, v as f64 / max_v, 0.));
+            }
+        }
+        buffer
+    }
+
+    /// Unit normal of the surface seen through the center of each pixel,
+    /// facing the camera, zero for the pixels showing the sky
+    pub fn render_normals(&self, world: &HittableList) -> Vec<Vec3> {
+        self.primary_hits(world, |rec| rec.map_or(Vec3::zero(), |rec| rec.normal))
+    }
+
+    /// Albedo guide buffer for denoisers: base color of the surface seen
+    /// through the center of each pixel, before any lighting, see
+    /// [`Material::base_color`]. Pixels showing the sky are black.
+    pub fn render_albedo(&self, world: &HittableList) -> Vec<Color> {
+        self.primary_hits(world, |rec| {
+            rec.map_or(Color::zero(), |rec| rec.material.base_color(rec))
+        })
+    }
+
+    /// Depth buffer as a grayscale image: the distance along the view
+    /// direction of the surface seen through the center of each pixel,
+    /// mapped from `[near, far]` to gray levels according to the
+    /// `depth_shading`. Closer or farther surfaces are clamped, the sky is at
+    /// the `far` distance. Meant to be written with [`ToneMap::None`].
+    pub fn render_depth(&self, world: &HittableList, near: f64, far: f64) -> Vec<Color> {
+        let forward = -self.basis().w;
+        let range = Interval::new(near, far);
+        self.primary_hits(world, |rec| {
+            let depth = rec.map_or(far, |rec| (rec.point - self.center).dot(&forward));
+            // an empty range would divide by zero
+            let fraction = if near == far {
+                0.0
+            } else {
+                range.inv_lerp(depth).clamp(0.0, 1.0)
+            };
+            let level = match self.depth_shading {
+                DepthShading::NearWhite => 1.0 - fraction,
+                DepthShading::NearBlack => fraction,
+            };
+            Color::new(level, level, level)
+        })
+    }
+
+    /// Id of the object hit by the primary ray through the center of each
+    /// pixel, see [`Tagged`](crate::hittable::Tagged). Pixels showing the sky or untagged objects are 0.
+    pub fn render_object_ids(&self, world: &HittableList) -> Vec<u32> {
+        self.primary_hits(world, |rec| rec.map_or(0, |rec| rec.object_id))
+    }
+
+    /// Maps the first hit of the ray through the center of each pixel, if
+    /// any, to a value of an auxiliary buffer in row-major order
+    fn primary_hits<T>(
+        &self,
+        world: &HittableList,
+        aov: impl Fn(Option<&HitRecord>) -> T,
+    ) -> Vec<T> {
+        let viewport_ctx = self.viewport_context();
+        let offset = Vec3::zero();
+
+        let mut buffer = Vec::with_capacity((self.image.width * self.image.height) as usize);
+        for v in 0..self.image.height {
+            for u in 0..self.image.width {
+                let ray = self.get_ray_at(u, v, &offset, &viewport_ctx);
+                buffer.push(aov(world.hit(&ray, Interval::positive()).as_ref()));
+            }
+        }
+
+        buffer
+    }
+
+    /// Number of box and primitive intersection tests performed by the
+    /// primary ray through the center of each pixel, in row-major order
+    pub fn bvh_test_counts(&self, bvh: &BvhNode) -> Vec<u32> {
+        let viewport_ctx = self.viewport_context();
+        let offset = Vec3::zero();
+
+        let mut counts = Vec::with_capacity((self.image.width * self.image.height) as usize);
+        for v in 0..self.image.height {
+            for u in 0..self.image.width {
+                let ray = self.get_ray_at(u, v, &offset, &viewport_ctx);
+                let mut tests = 0;
+                bvh.hit_counted(&ray, Interval::positive(), &mut tests);
+                counts.push(tests);
+            }
+        }
+
+        counts
+    }
+
+    /// Traversal cost view of a BVH: each pixel is colored from blue (few
+    /// intersection tests) through green to red (the most expensive pixel)
+    pub fn render_bvh_heatmap(&self, bvh: &BvhNode) -> Vec<Color> {
+        let counts = self.bvh_test_counts(bvh);
+        let max = counts.iter().copied().max().unwrap_or(0).max(1) as f64;
+
+        let blue = Color::new(0., 0., 1.);
+        let green = Color::new(0., 1., 0.);
+        let red = Color::new(1., 0., 0.);
+
+        counts
+            .iter()
+            .map(|&count| {
+                let t = count as f64 / max;
+                if t < 0.5 {
+                    lerp(&blue, &green, 2.0 * t)
+                } else {
+                    lerp(&green, &red, 2.0 * t - 1.0)
+                }
+            })
+            .collect()
+    }
+
+    /// Applies the `max_sample_luminance` clamp to a single sample, keeping
+    /// its hue
+    pub fn clamp_sample(&self, sample: Color) -> Color {
+        match self.max_sample_luminance {
+            Some(max) => {
+                let l = luminance(&sample);
+                if l > max { sample * (max / l) } else { sample }
+            }
+            None => sample,
+        }
+    }
+
+    pub fn ray_color(&self, ray: &Ray, world: &HittableList, depth: i16) -> Color {
+        self.trace(ray, world, depth).0
+    }
+
+    /// Computes the color of the ray like [`Camera::ray_color`], also
+    /// returning the first surface hit by the ray if any
+    pub fn trace(&self, ray: &Ray, world: &HittableList, depth: i16) -> (Color, Option<HitRecord>) {
+        let (mut color, rec) = self.trace_path(ray, world, depth, None);
+        if let Some((fog_color, density)) = self.fog
+            && let Some(rec) = &rec
+        {
+            let distance = rec.t * ray.direction().length();
+            color = lerp(&color, &fog_color, 1.0 - (-density * distance).exp());
+        }
+        (color, rec)
+    }
+
+    /// `scatter_pdf` is the density of the direction of the ray when it was
+    /// scattered by a surface that already sampled the `lights` directly, in
+    /// which case their emission is weighted against the direct samples
+    fn trace_path(
+        &self,
+        ray: &Ray,
+        world: &HittableList,
+        depth: i16,
+        scatter_pdf: Option<f64>,
+    ) -> (Color, Option<HitRecord>) {
+        if depth <= 0 {
+            return (Color::zero(), None);
+        }
+
+        self.check_finite("ray direction", ray.direction());
+        stats::count_intersection();
+        let bounds = Interval::new(self.min_hit_distance, f64::MAX);
+        if let Some(mut rec) = world.hit(ray, bounds) {
+            self.check_finite("hit point", &rec.point);
+            rec.normal = rec.material.shading_normal(&rec);
+            let mut emitted = rec.material.emitted(&rec);
+            if let Some(scatter_pdf) = scatter_pdf
+                && let Some(light_pdf) = self.sampled_light_pdf(&rec.material, ray)
+            {
+                emitted *= if self.mis {
+                    power_heuristic(scatter_pdf, light_pdf)
+                } else {
+                    0.0
+                };
+            }
+            let Some(scatter) = rec.material.scatter_with_mode(ray, &rec, self.diffuse_mode) else {
+                // a glossy bounce absorbed below the surface doesn't stop the
+                // lobe from reflecting the light sampled directly
+                if rec.material.glossy_lobe(ray, &rec, &rec.normal).is_some() {
+                    emitted += self.direct_light(ray, &rec, world);
+                }
+                return (emitted, Some(rec));
+            };
+
+            let direct = self.direct_light(ray, &rec, world);
+            let Some(attenuation) = self.roulette(scatter.attenuation, depth) else {
+                return (emitted + direct, Some(rec));
+            };
+
+            let scatter_pdf = if self.lights.is_empty() {
+                None
+            } else {
+                self.scatter_pdf(ray, &rec, scatter.scattered.direction())
+            };
+            let (indirect, _) = self.trace_path(&scatter.scattered, world, depth - 1, scatter_pdf);
+            let color = emitted + direct + attenuation * indirect;
+            return (color, Some(rec));
+        }
+
+        (self.sky(ray), None)
+    }
+
+    /// Color of a ray that doesn't hit anything, from the `environment` if
+    /// set
+    fn sky(&self, ray: &Ray) -> Color {
+        match &self.environment {
+            Some(environment) => environment.sample(ray.direction()),
+            None => Camera::background(ray),
+        }
+    }
+
+    /// With `debug_check` on, panics if `value` has a component that is NaN
+    /// or infinite, naming it and the pixel being sampled
+    fn check_finite(&self, what: &str, value: &Vec3) {
+        if self.debug_check && !value.components().all(f64::is_finite) {
+            let (u, v) = CURRENT_PIXEL.with(Cell::get);
+            panic!("Non finite {} {:?} at pixel ({}, {})", what, value, u, v);
+        }
+    }
+
+    /// Russian roulette on the path at `depth`: once the path has scattered
+    /// `rr_min_bounces` times it continues with a probability given by the
+    /// brightest channel of the attenuation, which is divided by that
+    /// probability to keep the estimate unbiased. None if the path ends.
+    fn roulette(&self, attenuation: Color, depth: i16) -> Option<Color> {
+        let bounces = (self.max_recursion_depth.max(1) - depth).max(0) as u32;
+        if !self.russian_roulette || bounces < self.rr_min_bounces {
+            return Some(attenuation);
+        }
+
+        let survival = attenuation.max_component().clamp(0.05, 1.0);
+        if normal_random() >= survival {
+            return None;
+        }
+        Some(attenuation / survival)
+    }
+
+    /// Density with which the `lights` whose geometry has `material` sample
+    /// the direction of `ray` from its origin, None if `material` doesn't
+    /// belong to any of them
+    fn sampled_light_pdf(&self, material: &Arc<dyn Material>, ray: &Ray) -> Option<f64> {
+        let mut lights = self
+            .lights
+            .iter()
+            .filter(|light| {
+                light
+                    .material()
+                    .is_some_and(|m| std::ptr::addr_eq(Arc::as_ptr(m), Arc::as_ptr(material)))
+            })
+            .peekable();
+        lights.peek()?;
+        Some(
+            lights
+                .map(|light| light.pdf(ray.origin(), ray.direction()))
+                .sum(),
+        )
+    }
+
+    /// Density with which the material at `rec` scatters the ray `ray_in`
+    /// along `direction`, None if its bounces don't sample the lights
+    /// directly: only the glossy lobes and the diffuse parts do
+    fn scatter_pdf(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Option<f64> {
+        match rec.material.glossy_lobe(ray_in, rec, direction) {
+            Some((_, pdf)) => Some(pdf),
+            None => (rec.material.diffuse_albedo(rec) != Color::zero())
+                .then(|| self.diffuse_mode.pdf(&rec.normal, direction)),
+        }
+    }
+
+    /// Light reaching the hit point straight from the `lights`, reflected
+    /// along `ray_in` toward the viewer by the glossy lobe or the diffuse
+    /// part of the material, averaged over `light_samples` samples of every
+    /// light
+    pub fn direct_light(&self, ray_in: &Ray, rec: &HitRecord, world: &HittableList) -> Color {
+        let mut color = Color::zero();
+        if self.lights.is_empty() {
+            return color;
+        }
+
+        let albedo = rec.material.diffuse_albedo(rec);
+        let samples = self.light_samples.max(1);
+        for light in self
+            .lights
+            .iter()
+            .flat_map(|l| iter::repeat_n(l, samples as usize))
+        {
+            let Some(sample) = light.incident(&rec.point) else {
+                continue;
+            };
+
+            let cosine = rec.normal.dot(&sample.direction);
+            if cosine <= 0.0 {
+                continue;
+            }
+
+            let shadow_ray = Ray::new(rec.point, sample.direction);
+            let shadow_bounds =
+                Interval::new(self.min_hit_distance, sample.distance - SHADOW_EPSILON);
+            stats::count_intersection();
+            if world.hit_any(&shadow_ray, shadow_bounds) {
+                continue;
+            }
+
+            // fraction of the light reflected and density of the bounces
+            // toward the light
+            let (reflected, scatter_pdf) =
+                match rec.material.glossy_lobe(ray_in, rec, &sample.direction) {
+                    Some((attenuation, pdf)) => (pdf * attenuation, pdf),
+                    None => (
+                        (cosine / PI) * albedo,
+                        self.diffuse_mode.pdf(&rec.normal, &sample.direction),
+                    ),
+                };
+            let weight = if self.mis {
+                power_heuristic(sample.pdf, scatter_pdf)
+            } else {
+                1.0
+            };
+            color += weight * reflected * sample.radiance;
+        }
+        color / samples as f64
+    }
+
+    /// Color of the sky seen by a ray that doesn't hit anything. Degenerate
+    /// directions (zero or not finite) see the bottom of the sky instead of
+    /// producing NaN.
+    pub fn background(ray: &Ray) -> Color {
+        let unit_direction = ray.direction().normal();
+        let blue = Color::new(0.5, 0.7, 1.0);
+        let white = Color::new(1.0, 1.0, 1.0);
+
+        let t = 0.5 * (unit_direction.y() + 1.0);
+        if !t.is_finite() {
+            return white;
+        }
+
+        lerp(&white, &blue, t)
+    }
+
+    pub fn get_ray(&self, u: i32, v: i32, viewport_ctx: &ViewportContext) -> Ray {
+        let offset = if self.jitter {
+            Camera::sample_square()
+        } else {
+            Vec3::zero()
+        };
+        self.get_ray_at(u, v, &offset, viewport_ctx)
+    }
+
+    /// Ray through the point at `offset` from the center of the pixel `(u, v)`
+    pub fn get_ray_at(&self, u: i32, v: i32, offset: &Vec3, viewport_ctx: &ViewportContext) -> Ray {
+        let pixel_sample = viewport_ctx.upper_left_pixel
+            + ((u as f64 + offset.x()) * viewport_ctx.delta_u
+                + (v as f64 + offset.y()) * viewport_ctx.delta_v);
+
+        let ray_origin = if self.defocus_radius > 0.0 {
+            let lens = self.defocus_radius * self.aperture_sample();
+            let basis = self.basis();
+            self.center + lens.x() * basis.u + lens.y() * basis.v
+        } else {
+            self.center
+        };
+        let ray_dir = pixel_sample - ray_origin;
+
+        let differentials = RayDifferentials {
+            dx: ray_dir + viewport_ctx.delta_u,
+            dy: ray_dir + viewport_ctx.delta_v,
+        };
+
+        Ray::with_differentials(ray_origin, ray_dir, differentials)
+    }
+
+    /// Ray from the camera center through the point of the viewport at the
+    /// normalized device coordinates `(ndc_x, ndc_y)`, `(-1, -1)` being the
+    /// bottom left corner and `(1, 1)` the top right one. No jitter nor
+    /// defocus, e.g. to pick the object under the mouse.
+    pub fn ray_through_ndc(&self, ndc_x: f64, ndc_y: f64) -> Ray {
+        let basis = self.basis();
+        let viewport_center = self.center - self.focal_length * basis.w;
+        let point = viewport_center
+            + (0.5 * ndc_x * self.viewport_width) * basis.u
+            + (0.5 * ndc_y * self.viewport_height) * basis.v;
+        Ray::new(self.center, point - self.center)
+    }
+
+    /// Random shift applied to all the offsets of a pixel when the sampler is
+    /// a low-discrepancy sequence, otherwise every pixel would use exactly
+    /// the same pattern
+    fn sample_rotation(&self) -> (f64, f64) {
+        match self.sampler {
+            Sampler::Random => (0., 0.),
+            _ => (normal_random(), normal_random()),
+        }
+    }
+
+    /// Offset of the `index`-th sample of a pixel from the pixel center, in
+    /// the square `[-0.5, 0.5] x [-0.5, 0.5] x {0}`, zero without `jitter`
+    pub fn pixel_offset(&self, index: u32, rotation: (f64, f64)) -> Vec3 {
+        if !self.jitter {
+            return Vec3::zero();
+        }
+        let (x, y) = self.sampler.sample_2d(index);
+        Vec3::new(
+            (x + rotation.0).fract() - 0.5,
+            (y + rotation.1).fract() - 0.5,
+            0.,
+        )
+    }
+
+    /// Random point of the aperture of unit radius on the `z = 0` plane: the
+    /// unit disk, or the regular polygon with `aperture_blades` sides
+    /// inscribed in it with a vertex on the x axis
+    pub fn aperture_sample(&self) -> Vec3 {
+        if self.aperture_blades < 3 {
+            loop {
+                let p = Vec3::new(2. * normal_random() - 1., 2. * normal_random() - 1., 0.);
+                if p.length2() < 1.0 {
+                    return p;
+                }
+            }
+        }
+
+        // uniform point of the triangle between the center and a random side
+        let blades = self.aperture_blades as f64;
+        let side = (normal_random() * blades).floor().min(blades - 1.0);
+        let vertex = |i: f64| {
+            let angle = 2.0 * PI * i / blades;
+            Vec3::new(angle.cos(), angle.sin(), 0.)
+        };
+        let (a, b) = (vertex(side), vertex(side + 1.0));
+
+        let (mut s, mut t) = (normal_random(), normal_random());
+        if s + t > 1.0 {
+            (s, t) = (1.0 - s, 1.0 - t);
+        }
+        s * a + t * b
+    }
+
+    /// Returns a random point in the square `[-0.5, 0.5] x [-0.5, 0.5] x {0}`
+    pub fn sample_square() -> Vec3 {
+        Vec3::new(normal_random() - 0.5, normal_random() - 0.5, 0.)
+    }
+}
+
+#[cfg(test)]
+mod ppm_tests {
+    use super::*;
+
+    fn written_red(c: &Color, tone_map: ToneMap) -> i32 {
+        written_red_max(c, tone_map, ppm::MAX_VALUE_8)
+    }
+
+    fn written_red_max(c: &Color, tone_map: ToneMap, max_value: u16) -> i32 {
+        let mut out = Vec::new();
+        ppm::write_color(&mut out, c, tone_map, max_value).unwrap();
+        if max_value > ppm::MAX_VALUE_8 {
+            assert_eq!(out.len(), 6);
+            return u16::from_be_bytes([out[0], out[1]]) as i32;
+        }
+        let out = String::from_utf8(out).unwrap();
+        out.split_whitespace().next().unwrap().parse().unwrap()
+    }
+
+    #[test]
+    fn gamma_toggle() {
+        let gray = Color::new(0.25, 0.25, 0.25);
+        assert!((written_red(&gray, ToneMap::None) - 64).abs() <= 1);
+        assert!((written_red(&gray, ToneMap::Gamma2) - 128).abs() <= 1);
+    }
+
+    #[test]
+    fn hue_preserving_clamp() {
+        let orange = Color::new(2.0, 1.0, 0.0);
+        let clamped = ClampMode::PreserveHue.apply(&orange);
+
+        let mut out = Vec::new();
+        ppm::write_color(&mut out, &clamped, ToneMap::None, ppm::MAX_VALUE_8).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "254 127 0 ");
+        assert_eq!(ClampMode::PerChannel.apply(&orange), orange);
+    }
+
+    #[test]
+    fn sixteen_bits() {
+        let mut out = Vec::new();
+        ppm::header(&mut out, &Image::new(4, 2).unwrap(), ppm::MAX_VALUE_16).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "P6\n4 2\n65535\n");
+
+        let gray = Color::new(0.5, 0.5, 0.5);
+        let red = written_red_max(&gray, ToneMap::None, ppm::MAX_VALUE_16);
+        assert!((red - 32768).abs() <= 1);
+
+        let white = Color::new(2., 2., 2.);
+        assert_eq!(
+            written_red_max(&white, ToneMap::None, ppm::MAX_VALUE_16),
+            65535
+        );
+
+        // big-endian samples right after the header, no row separators
+        let mut out = Vec::new();
+        let img = Image::new(2, 1).unwrap();
+        let row = [white, Color::zero()];
+        ppm::write(&mut out, &img, &row, ToneMap::None, ppm::MAX_VALUE_16).unwrap();
+        let header = b"P6\n2 1\n65535\n";
+        assert_eq!(&out[..header.len()], header);
+        assert_eq!(
+            &out[header.len()..],
+            &[255, 255, 255, 255, 255, 255, 0, 0, 0, 0, 0, 0]
+        );
+    }
+}
+
+#[cfg(test)]
+mod camera_tests {
+    use super::*;
+    use crate::aabb::Aabb;
+    use crate::hittable::Tagged;
+    use crate::light::SpotLight;
+    use crate::material::{DiffuseLight, Glossy, Lambertian};
+    use crate::png;
+    use crate::quad::{Ground, Quad};
+    use crate::sphere::Sphere;
+
+    fn test_camera() -> Camera {
+        let mut camera = Camera::new(Image::new(23, 13).unwrap());
+        camera.focal_length = 1.0;
+        camera.set_viewport_from_height(2.0);
+        camera.sample_per_pixel = 4;
+        camera.max_recursion_depth = 4;
+        camera.tile_size = 4;
+        camera
+    }
+
+    /// Mean and variance of the luminance of `n` reseeded samples of `ray`
+    fn luminance_stats(
+        camera: &Camera,
+        ray: &Ray,
+        world: &HittableList,
+        depth: i16,
+        n: u64,
+    ) -> (f64, f64) {
+        let samples: Vec<f64> = (0..n)
+            .map(|i| {
+                random::seed(i);
+                luminance(&camera.ray_color(ray, world, depth))
+            })
+            .collect();
+        let n = samples.len() as f64;
+        let mean = samples.iter().sum::<f64>() / n;
+        let variance = samples.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / n;
+        (mean, variance)
+    }
+
+    fn test_world() -> HittableList {
+        let mut world = HittableList::new();
+        world.add(Sphere::from_center_radius(0., 0., -1., 0.5));
+        world.add(Sphere::from_center_radius(0., -100.5, -1., 100.0));
+        world
+    }
+
+    #[test]
+    fn tiled_render_matches_serial() {
+        let mut camera = test_camera();
+        let world = test_world();
+
+        let serial = camera.render_buffer(&world);
+        let tiled = camera.render_buffer_tiled(&world);
+
+        assert_eq!(serial.len(), 23 * 13);
+        assert_eq!(serial, tiled);
+
+        for threads in [1, 3, 8] {
+            camera.threads = Some(threads);
+            assert_eq!(camera.worker_threads(), threads);
+            assert_eq!(camera.render_buffer_tiled(&world), serial);
+        }
+        camera.threads = Some(0);
+        assert_eq!(camera.worker_threads(), 1);
+    }
+
+    #[test]
+    fn parallel_render_is_byte_identical() {
+        let world = test_world();
+
+        for (seed, tile_size) in [(0, 1), (7, 3), (7, 64)] {
+            let mut camera = test_camera();
+            camera.seed = seed;
+            camera.tile_size = tile_size;
+
+            let mut serial = Vec::new();
+            let buffer = camera.render_buffer(&world);
+            let (tone_map, max) = (camera.tone_map, camera.ppm_max_value);
+            ppm::write(&mut serial, &camera.image, &buffer, tone_map, max).unwrap();
+
+            let mut parallel = Vec::new();
+            camera.render(&mut parallel, &world).unwrap();
+            assert_eq!(serial, parallel);
+        }
+
+        let mut camera = test_camera();
+        let first = camera.render_buffer(&world);
+        camera.seed = 1;
+        assert_ne!(camera.render_buffer(&world), first);
+    }
+
+    fn center_ray_direction(camera: &Camera) -> Vec3 {
+        random::seed(0);
+        let ray = camera.get_ray(11, 6, &camera.viewport_context());
+        ray.direction().normal()
+    }
+
+    #[test]
+    fn default_basis() {
+        let basis = test_camera().basis();
+        assert_eq!(basis.u, Vec3::new(1., 0., 0.));
+        assert_eq!(basis.v, Vec3::new(0., 1., 0.));
+        assert_eq!(basis.w, Vec3::new(0., 0., 1.));
+    }
+
+    #[test]
+    fn moving_look_from_changes_rays() {
+        let mut camera = test_camera();
+        let before = center_ray_direction(&camera);
+        assert!(before.dot(&Vec3::new(0., 0., -1.)) > 0.99);
+
+        camera.set_look_from(Point3::new(-2., 0., -1.));
+        let after = center_ray_direction(&camera);
+
+        assert_ne!(before, after);
+        // the camera keeps looking at (0, 0, -1), so rays now go along +x
+        assert!(after.dot(&Vec3::new(1., 0., 0.)) > 0.99);
+    }
+
+    #[test]
+    fn firefly_clamp() {
+        let mut camera = test_camera();
+        let firefly = Color::new(1000., 1000., 1000.);
+        let dim = Color::new(0.5, 0.5, 0.5);
+
+        assert_eq!(camera.clamp_sample(firefly), firefly);
+
+        camera.max_sample_luminance = Some(2.0);
+        let clamped = camera.clamp_sample(firefly);
+        assert!((luminance(&clamped) - 2.0).abs() < 1e-9);
+        assert_eq!(camera.clamp_sample(dim), dim);
+
+        let average = (clamped + camera.clamp_sample(dim)) / 2.0;
+        assert!((average.x() - 1.25).abs() < 1e-9);
+    }
+
+    #[test]
+    fn uv_test_pattern() {
+        let camera = test_camera();
+        let buffer = camera.render_uv_test();
+
+        assert_eq!(buffer.len(), 23 * 13);
+        assert_eq!(buffer[0], Color::zero());
+        assert_eq!(buffer[22], Color::new(1., 0., 0.));
+        assert_eq!(buffer[12 * 23], Color::new(0., 1., 0.));
+        assert_eq!(buffer[23 * 13 - 1], Color::new(1., 1., 0.));
+    }
+
+    #[test]
+    fn render_stats() {
+        let camera = test_camera();
+        let (pixels, stats) = camera.render_pixels_with_stats(&test_world());
+
+        assert_eq!(pixels, camera.render_pixels(&test_world()));
+        assert_eq!(stats.pixels, 23 * 13);
+        assert_eq!(stats.counts.primary_rays, 23 * 13 * 4);
+        assert!(stats.counts.intersection_calls > stats.counts.primary_rays);
+        assert!(stats.wall_time.as_nanos() > 0);
+        assert!(stats.mrays_per_second() > 0.0);
+    }
+
+    #[test]
+    fn axes_overlay() {
+        let mut camera = test_camera();
+        camera.look_at(
+            Point3::new(3., 2., 3.),
+            Point3::zero(),
+            Vec3::new(0., 1., 0.),
+        );
+        let world = HittableList::new();
+
+        let plain = camera.render_buffer(&world);
+        camera.draw_axes = true;
+        let with_axes = camera.render_buffer(&world);
+
+        let width = camera.image.width;
+        let (u, v) = camera.project(&Point3::new(0.5, 0., 0.)).unwrap();
+        let near_x_axis = (v.round() as i32 * width + u.round() as i32) as usize;
+        assert_ne!(plain[near_x_axis], with_axes[near_x_axis]);
+        assert!(with_axes[near_x_axis].x() > plain[near_x_axis].x());
+
+        // the origin projects at the center of the image
+        let (u, v) = camera.project(&Point3::zero()).unwrap();
+        assert!((u - 11.).abs() < 1e-9 && (v - 6.).abs() < 1e-9);
+
+        for far in [0, 22, 12 * 23, 12 * 23 + 22] {
+            assert_eq!(plain[far], with_axes[far]);
+        }
+    }
+
+    #[test]
+    fn collected_lights_are_not_counted_twice() {
+        let mut world = HittableList::new();
+        world.add(Ground::new(-0.5, 50.));
+        world.add(
+            Quad::new(
+                Point3::new(-0.5, 1., -1.5),
+                Vec3::new(1., 0., 0.),
+                Vec3::new(0., 0., 1.),
+            )
+            .with_material(DiffuseLight::new(Color::new(1., 1., 1.), 4.)),
+        );
+
+        let mut camera = test_camera();
+        camera.image = Image::new(4, 3).unwrap();
+        camera.set_viewport_from_height(2.0);
+        camera.look_at(
+            Point3::zero(),
+            Point3::new(0., -0.5, -1.),
+            Vec3::new(0., 1., 0.),
+        );
+        camera.sample_per_pixel = 400;
+        camera.max_recursion_depth = 2;
+        let mean = |c: &Camera| {
+            c.render_pixels(&world)
+                .iter()
+                .map(|p| p.color.x())
+                .sum::<f64>()
+        };
+
+        let unsampled = mean(&camera);
+        camera.lights = world.collect_lights();
+        let sampled = mean(&camera);
+        assert!(
+            (sampled / unsampled - 1.0).abs() < 0.1,
+            "{} vs {}",
+            sampled,
+            unsampled
+        );
+    }
+
+    #[test]
+    fn progressive_frames() {
+        let mut camera = test_camera();
+        camera.outline = Some(Color::zero());
+        let world = test_world();
+
+        let mut passes = Vec::new();
+        let mut last = Vec::new();
+        camera.render_progressive(&world, |frame, pass| {
+            passes.push(pass);
+            last = frame.to_vec();
+        });
+
+        assert_eq!(passes, vec![1, 2, 3, 4]);
+        assert_eq!(last, camera.render_buffer(&world));
+    }
+
+    #[test]
+    fn interlaced_passes() {
+        let camera = test_camera();
+        let world = test_world();
+        let width = camera.image.width as usize;
+
+        let mut frames = Vec::new();
+        camera.render_interlaced(&world, |frame, pass| frames.push((pass, frame.to_vec())));
+        assert_eq!(frames.len(), 2);
+
+        let (pass, first) = &frames[0];
+        assert_eq!(*pass, 1);
+        let rows: Vec<&[Color]> = first.chunks(width).collect();
+        for v in (1..rows.len()).step_by(2) {
+            assert_eq!(rows[v], rows[v - 1]);
+        }
+
+        let final_frame = camera.render_buffer(&world);
+        assert_eq!(frames[1], (2, final_frame.clone()));
+        assert_eq!(rows[2], &final_frame[2 * width..3 * width]);
+    }
+
+    #[test]
+    fn time_budget() {
+        let mut camera = test_camera();
+        let world = test_world();
+
+        let (buffer, samples) = camera.render_timed(&world, Duration::ZERO);
+        assert_eq!(samples, 1);
+        assert_eq!(buffer.len(), 23 * 13);
+
+        // post-processed like the other renders
+        camera.sample_per_pixel = 1;
+        camera.outline = Some(Color::zero());
+        camera.flip_vertical = true;
+        let (buffer, _) = camera.render_timed(&world, Duration::ZERO);
+        assert_eq!(buffer, camera.render_buffer(&world));
+
+        let (_, more) = camera.render_timed(&world, Duration::from_millis(100));
+        assert!(more > 1);
+    }
+
+    #[test]
+    fn minimum_samples_and_depth() {
+        let world = test_world();
+        let mut camera = test_camera();
+        camera.sample_per_pixel = 1;
+        camera.max_recursion_depth = 1;
+        let minimum = camera.render_buffer(&world);
+
+        camera.sample_per_pixel = 0;
+        camera.max_recursion_depth = -3;
+        let clamped = camera.render_buffer(&world);
+        assert!(clamped.iter().all(|c| c.components().all(f64::is_finite)));
+        assert_eq!(clamped, minimum);
+    }
+
+    #[test]
+    fn object_id_buffer() {
+        let mut world = HittableList::new();
+        world.add(Tagged::new(
+            Sphere::from_center_radius(-0.6, 0., -1., 0.5),
+            1,
+        ));
+        world.add(Tagged::new(
+            Sphere::from_center_radius(0.6, 0., -1., 0.5),
+            2,
+        ));
+
+        let camera = test_camera();
+        let ids = camera.render_object_ids(&world);
+        let width = camera.image.width as usize;
+        let middle_row = &ids[6 * width..7 * width];
+
+        assert_eq!(middle_row[0], 0);
+        assert_eq!(middle_row[7], 1);
+        assert_eq!(middle_row[15], 2);
+        assert!(ids.contains(&1) && ids.contains(&2));
+    }
+
+    #[test]
+    fn animation_frames() {
+        let camera = test_camera();
+        let out_dir = std::env::temp_dir().join(format!("rt-animation-{}", std::process::id()));
+
+        let paths = camera
+            .render_animation(
+                3,
+                |t| {
+                    let mut world = HittableList::new();
+                    // moving along x only, the center pixel would see the same
+                    // sky gradient in every frame
+                    world.add(Sphere::at([0.9 * t - 0.3, 0.6 * t - 0.2, -1.], 0.5));
+                    world
+                },
+                &out_dir,
+            )
+            .unwrap();
+        assert_eq!(paths.len(), 3);
+
+        let center_pixels: Vec<String> = paths
+            .iter()
+            .map(|path| {
+                let ppm = fs::read_to_string(path).unwrap();
+                // the header takes the first 3 lines, then one line per row
+                let row = ppm.lines().nth(3 + 6).unwrap().to_string();
+                let values: Vec<&str> = row.split_whitespace().collect();
+                values[3 * 11..3 * 12].join(" ")
+            })
+            .collect();
+        fs::remove_dir_all(&out_dir).unwrap();
+
+        assert_ne!(center_pixels[0], center_pixels[1]);
+        assert_ne!(center_pixels[1], center_pixels[2]);
+        assert_ne!(center_pixels[0], center_pixels[2]);
+    }
+
+    #[test]
+    fn aperture_shapes() {
+        let mut camera = test_camera();
+        let samples = |camera: &Camera| {
+            (0..1000)
+                .map(|_| camera.aperture_sample())
+                .collect::<Vec<_>>()
+        };
+
+        assert!(
+            samples(&camera)
+                .iter()
+                .all(|p| p.length() < 1.0 && p.z() == 0.0)
+        );
+
+        // inside the hexagon when on the inner side of all the edges, whose
+        // normals are halfway between the vertices
+        camera.aperture_blades = 6;
+        let apothem = (PI / 6.0).cos();
+        let hexagon = samples(&camera);
+        for p in &hexagon {
+            for k in 0..6 {
+                let angle = (2 * k + 1) as f64 * PI / 6.0;
+                let normal = Vec3::new(angle.cos(), angle.sin(), 0.);
+                assert!(p.dot(&normal) <= apothem + 1e-12);
+            }
+        }
+        // some samples outside the inscribed circle, near the vertices
+        assert!(hexagon.iter().any(|p| p.length() > apothem));
+    }
+
+    #[test]
+    fn defocus_blur() {
+        let world = test_world();
+        let mut camera = test_camera();
+        let sharp = camera.render_buffer(&world);
+
+        camera.defocus_radius = 0.1;
+        camera.aperture_blades = 5;
+        let blurred = camera.render_buffer(&world);
+        assert_ne!(sharp, blurred);
+
+        // rays still pass through the pixel on the focus plane
+        random::seed(3);
+        let ctx = camera.viewport_context();
+        let ray = camera.get_ray_at(4, 2, &Vec3::zero(), &ctx);
+        let on_focus = *ray.origin() + *ray.direction();
+        let pixel = ctx.upper_left_pixel + 4.0 * ctx.delta_u + 2.0 * ctx.delta_v;
+        assert!(on_focus.approx_eq(&pixel, 1e-12));
+        assert!(*ray.origin() != camera.look_from());
+    }
+
+    #[test]
+    fn roulette_min_bounces() {
+        let world = test_world();
+        let mut camera = test_camera();
+        camera.max_recursion_depth = 6;
+        let full = camera.render_buffer(&world);
+
+        camera.russian_roulette = true;
+        camera.rr_min_bounces = 6;
+        assert_eq!(camera.render_buffer(&world), full);
+
+        camera.rr_min_bounces = 0;
+        assert_ne!(camera.render_buffer(&world), full);
+    }
+
+    #[test]
+    fn sphere_outline() {
+        let mut world = HittableList::new();
+        world.add(Sphere::at([0., 0., -1.], 0.4));
+
+        let mut camera = test_camera();
+        camera.image = Image::new(92, 52).unwrap();
+        camera.set_viewport_from_height(2.0);
+        camera.sample_per_pixel = 1;
+        let normals = camera.render_normals(&world);
+        let black = Color::zero();
+        camera.outline = Some(black);
+        let buffer = camera.render_buffer(&world);
+
+        let (width, height) = (92, 52);
+        let is_sky = |i: usize| normals[i] == Vec3::zero();
+        let mut outlined = 0;
+        for (i, c) in buffer.iter().enumerate() {
+            let (u, v) = (i % width, i / width);
+            let on_boundary = [(0, 1), (2, 1), (1, 0), (1, 2)].iter().any(|&(du, dv)| {
+                let (nu, nv) = ((u + du).wrapping_sub(1), (v + dv).wrapping_sub(1));
+                nu < width && nv < height && is_sky(nv * width + nu) != is_sky(i)
+            });
+            if on_boundary {
+                assert_eq!(*c, black, "pixel ({}, {})", u, v);
+                outlined += 1;
+            }
+        }
+        assert!(outlined > 50);
+        assert_ne!(buffer[0], black);
+        assert_ne!(buffer[26 * width + 46], black);
+    }
+
+    #[test]
+    fn invalid_configuration() {
+        let world = test_world();
+        let mut out = Vec::new();
+
+        let camera = Camera::new(Image::new(4, 4).unwrap());
+        let err = camera.render(&mut out, &world).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(out.is_empty());
+
+        let mut camera = test_camera();
+        assert!(camera.validate().is_ok());
+        camera.viewport_width = 0.0;
+        assert!(camera.validate().unwrap_err().contains("Viewport"));
+
+        let mut camera = test_camera();
+        camera.sample_per_pixel = 0;
+        assert!(camera.render(&mut out, &world).is_err());
+    }
+
+    #[test]
+    fn flipped_output() {
+        let mut camera = test_camera();
+        camera.sample_per_pixel = 1;
+        let world = test_world();
+        let width = camera.image.width as usize;
+        let buffer = camera.render_buffer(&world);
+
+        camera.flip_vertical = true;
+        let flipped = camera.render_buffer(&world);
+        let rows: Vec<&[Color]> = buffer.chunks(width).collect();
+        let flipped_rows: Vec<&[Color]> = flipped.chunks(width).rev().collect();
+        assert_eq!(rows, flipped_rows);
+
+        camera.flip_vertical = false;
+        camera.flip_horizontal = true;
+        let mirrored = camera.render_buffer(&world);
+        assert_eq!(mirrored[0], buffer[width - 1]);
+        assert_eq!(mirrored[width], buffer[2 * width - 1]);
+    }
+
+    #[test]
+    fn resume_from_checkpoint() {
+        let mut camera = test_camera();
+        camera.sample_per_pixel = 100;
+        let world = test_world();
+        let straight = camera.render_pixels(&world);
+
+        let mut acc = Accumulator::new(camera.image);
+        camera.accumulate(&world, &mut acc, 50);
+        let mut file = Vec::new();
+        acc.save(&mut file).unwrap();
+
+        let mut resumed = Accumulator::load(&mut file.as_slice()).unwrap();
+        camera.accumulate(&world, &mut resumed, 100);
+        assert_eq!(resumed.pixels(), straight);
+
+        // same through a checkpoint file, interrupted after 50 samples
+        let path = std::env::temp_dir().join(format!("rt-checkpoint-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        camera.sample_per_pixel = 50;
+        let half = camera.render_with_checkpoints(&world, &path, 20).unwrap();
+        assert_eq!(half, acc.pixels());
+
+        // the checkpoint can't be resumed with another seed or other settings
+        camera.sample_per_pixel = 100;
+        camera.seed += 1;
+        let err = camera
+            .render_with_checkpoints(&world, &path, 20)
+            .unwrap_err();
+        assert!(err.to_string().contains("seed"), "{}", err);
+        camera.seed -= 1;
+        camera.max_recursion_depth += 1;
+        assert!(camera.render_with_checkpoints(&world, &path, 20).is_err());
+        camera.max_recursion_depth -= 1;
+
+        let full = camera.render_with_checkpoints(&world, &path, 20).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(full, straight);
+    }
+
+    #[test]
+    fn alpha_coverage() {
+        let camera = test_camera();
+        let mut world = HittableList::new();
+        world.add(Sphere::from_center_radius(0., 0., -1., 0.5));
+
+        let pixels = camera.render_pixels(&world);
+        let width = camera.image.width as usize;
+
+        // top-left corner only sees the sky, the center is inside the sphere
+        assert_eq!(pixels[0].alpha, 0.0);
+        assert_eq!(pixels[6 * width + 11].alpha, 1.0);
+    }
+
+    #[test]
+    fn differentials_scale_with_resolution() {
+        let low = test_camera();
+        let mut high = test_camera();
+        high.image = Image::new(46, 26).unwrap();
+        high.set_viewport_from_height(2.0);
+
+        let footprint = |camera: &Camera| {
+            let ray = camera.get_ray(0, 0, &camera.viewport_context());
+            ray.footprint(1.0).unwrap()
+        };
+
+        let (low_x, low_y) = footprint(&low);
+        let (high_x, high_y) = footprint(&high);
+        assert!((low_x / high_x - 2.0).abs() < 1e-9);
+        assert!((low_y / high_y - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn set_center_translates_rays() {
+        let mut camera = test_camera();
+        let delta = Vec3::new(1., -2., 3.);
+
+        let rays = |camera: &Camera| {
+            let ctx = camera.viewport_context();
+            let offset = Vec3::zero();
+            [(0, 0), (11, 6), (22, 12)].map(|(u, v)| camera.get_ray_at(u, v, &offset, &ctx))
+        };
+
+        let before = rays(&camera);
+        camera.set_center(camera.center + delta);
+        let after = rays(&camera);
+
+        for (b, a) in before.iter().zip(after.iter()) {
+            assert!(a.origin().approx_eq(&(*b.origin() + delta), 1e-12));
+            assert!(a.direction().approx_eq(b.direction(), 1e-12));
+        }
+    }
+
+    #[test]
+    fn bvh_heatmap() {
+        let camera = test_camera();
+        let mut world = HittableList::new();
+        // dense cluster in front of the camera
+        for i in 0..5 {
+            for j in 0..5 {
+                let (x, y) = (i as f64 * 0.1 - 0.2, j as f64 * 0.1 - 0.2);
+                world.add(Sphere::from_center_radius(x, y, -2., 0.05));
+            }
+        }
+        let width = camera.image.width as usize;
+        let bvh = BvhNode::new(world);
+
+        let counts = camera.bvh_test_counts(&bvh);
+        let empty = counts[0];
+        let dense = counts[6 * width + 11];
+        assert!(empty < dense);
+
+        let heatmap = camera.render_bvh_heatmap(&bvh);
+        assert!(heatmap[0].z() > heatmap[0].x());
+        assert!(heatmap[6 * width + 11].z() < heatmap[0].z());
+    }
+
+    #[test]
+    fn albedo_aov() {
+        let red = Color::new(0.8, 0.1, 0.1);
+        let mut world = HittableList::new();
+        world.add(Sphere::at([0., 0., -1.], 0.5).with_material(Lambertian::new(red)));
+
+        let mut camera = test_camera();
+        let center = 6 * 23 + 11;
+        let albedo = camera.render_albedo(&world);
+        assert_eq!(albedo[center], red);
+        assert_eq!(albedo[0], Color::zero());
+
+        // the lights change the image but not the albedo
+        let unlit = camera.render_buffer(&world)[center];
+        camera.lights.push(Arc::new(SpotLight::new(
+            Point3::new(0., 0., 1.),
+            Vec3::new(0., 0., -1.),
+            0.2,
+            0.3,
+            Color::new(10., 10., 10.),
+        )));
+        assert_ne!(camera.render_buffer(&world)[center], unlit);
+        assert_eq!(camera.render_albedo(&world), albedo);
+    }
+
+    #[test]
+    fn spot_light_direct_lighting() {
+        let mut camera = test_camera();
+        camera.lights.push(Arc::new(SpotLight::new(
+            Point3::new(0., 3., -1.),
+            Vec3::new(0., -1., 0.),
+            0.2,
+            0.3,
+            Color::new(10., 10., 10.),
+        )));
+
+        let ground = Sphere::from_center_radius(0., -100.5, -1., 100.0);
+        let down = Ray::new(Point3::new(2., 1., -1.), Vec3::new(0., -1., 0.));
+        let under = Ray::new(Point3::new(0., 1., -1.), Vec3::new(0., -1., 0.));
+
+        let mut world = HittableList::new();
+        world.add(Sphere::from_center_radius(0., -100.5, -1., 100.0));
+
+        // outside of the cone, then right under the light
+        let rec = ground.hit(&down, Interval::positive()).unwrap();
+        assert_eq!(camera.direct_light(&down, &rec, &world), Color::zero());
+        let rec = ground.hit(&under, Interval::positive()).unwrap();
+        assert!(camera.direct_light(&under, &rec, &world).x() > 0.0);
+
+        // a sphere between the light and the ground casts a shadow
+        world.add(Sphere::from_center_radius(0., 1.5, -1., 0.5));
+        assert_eq!(camera.direct_light(&under, &rec, &world), Color::zero());
+    }
+
+    #[test]
+    fn one_sided_light_shading() {
+        let camera = test_camera();
+        let white = Color::new(1., 1., 1.);
+        let mut world = HittableList::new();
+        world
+            .add(Sphere::new(Point3::zero(), 1.).with_material(DiffuseLight::one_sided(white, 1.)));
+
+        let outside = Ray::new(Point3::new(0., 0., 5.), Vec3::new(0., 0., -1.));
+        let inside = Ray::new(Point3::zero(), Vec3::new(0., 0., -1.));
+        assert_eq!(camera.ray_color(&outside, &world, 4), white);
+        assert_eq!(camera.ray_color(&inside, &world, 4), Color::zero());
+    }
+
+    #[test]
+    fn frame_single_sphere() {
+        let mut camera = test_camera();
+        let mut world = HittableList::new();
+        world.add(Sphere::from_center_radius(2., 1., -5., 1.));
+
+        camera.frame_scene(&world);
+
+        let sphere_center = Point3::new(2., 1., -5.);
+        assert_eq!(camera.target(), sphere_center);
+
+        // The bounding sphere of the box (radius sqrt(3)) fits the 90 degrees
+        // vertical field of view
+        let distance = camera.look_from().distance(&sphere_center);
+        let expected = 3f64.sqrt() / (std::f64::consts::PI / 4.).sin();
+        assert!((distance - expected).abs() < 1e-9);
+
+        // The center pixel looks straight at the sphere
+        let direction = center_ray_direction(&camera);
+        let to_sphere = (sphere_center - camera.look_from()).normal();
+        assert!(direction.dot(&to_sphere) > 0.99);
+        assert!(
+            world
+                .hit(
+                    &Ray::new(camera.look_from(), direction),
+                    Interval::positive()
+                )
+                .is_some()
+        );
+    }
+
+    /// Sphere whose hit points and normals are all NaN
+    struct NanSphere(Sphere);
+
+    impl Hittable for NanSphere {
+        fn hit(&self, ray: &Ray, bounds: Interval) -> Option<HitRecord> {
+            let mut rec = self.0.hit(ray, bounds)?;
+            rec.point = Point3::new(f64::NAN, 0., 0.);
+            rec.normal = Vec3::new(f64::NAN, 0., 0.);
+            Some(rec)
+        }
+
+        fn bounding_box(&self) -> Aabb {
+            self.0.bounding_box()
+        }
+    }
+
+    fn nan_world() -> HittableList {
+        let mut world = HittableList::new();
+        world.add(NanSphere(Sphere::from_center_radius(0., 0., -1., 0.5)));
+        world
+    }
+
+    #[test]
+    fn nan_unchecked_by_default() {
+        let camera = test_camera();
+        // the NaN directions see the bottom of the sky, the render goes on
+        let pixels = camera.render_pixels(&nan_world());
+        assert!(
+            pixels
+                .iter()
+                .all(|p| p.color.components().all(f64::is_finite))
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Non finite hit point")]
+    fn debug_check_catches_nan() {
+        let mut camera = test_camera();
+        camera.debug_check = true;
+        camera.render_pixels(&nan_world());
+    }
+
+    #[test]
+    fn streaming_render() {
+        let world = test_world();
+        let mut camera = test_camera();
+        camera.threads = Some(3);
+
+        let mut peaks = Vec::new();
+        for height in [13, 130] {
+            camera.image = Image::new(23, height).unwrap();
+            let mut expected = Vec::new();
+            camera.render(&mut expected, &world).unwrap();
+
+            let mut streamed = Vec::new();
+            peaks.push(camera.render_streaming(&mut streamed, &world).unwrap());
+            assert_eq!(streamed, expected);
+        }
+
+        // the rows in memory are bounded by the threads, not by the height
+        assert!(peaks.iter().all(|&peak| peak <= 6), "{:?}", peaks);
+    }
+
+    #[test]
+    fn mis_variance() {
+        let mut world = HittableList::new();
+        world.add(Ground::new(0., 50.));
+        // large dim panel right above the lit point, small bright one aside
+        let panel = |corner: Point3, size: f64, intensity: f64| {
+            Quad::new(corner, Vec3::new(size, 0., 0.), Vec3::new(0., 0., size))
+                .with_material(DiffuseLight::one_sided(Color::new(1., 1., 1.), intensity))
+        };
+        world.add(panel(Point3::new(-1., 1., -1.), 2., 1.));
+        world.add(panel(Point3::new(2., 1., 0.), 0.1, 200.));
+
+        let ray = Ray::new(Point3::new(0., 0.5, 3.), Vec3::new(0., -0.5, -3.));
+        let estimate = |camera: &Camera| luminance_stats(camera, &ray, &world, 2, 4000);
+
+        let mut camera = test_camera();
+        let (brdf_mean, brdf_variance) = estimate(&camera);
+        camera.lights = world.collect_lights();
+        camera.mis = false;
+        let (light_mean, light_variance) = estimate(&camera);
+        camera.mis = true;
+        let (mis_mean, mis_variance) = estimate(&camera);
+
+        // same expected value, MIS at least as good as the best strategy
+        assert!((light_mean / brdf_mean - 1.).abs() < 0.1);
+        assert!((mis_mean / light_mean - 1.).abs() < 0.05);
+        assert!(mis_variance <= brdf_variance);
+        assert!(mis_variance <= light_variance);
+    }
+
+    #[test]
+    fn halton_bounces() {
+        // diffuse ground lit by the sky gradient, the primary rays fixed so
+        // that all the noise comes from the bounces
+        let mut world = HittableList::new();
+        world.add(Ground::new(-0.5, 50.).with_material(Lambertian::new(Color::new(0.5, 0.5, 0.5))));
+        let mut camera = test_camera();
+        camera.jitter = false;
+        camera.sample_per_pixel = 16;
+        camera.max_recursion_depth = 2;
+
+        let pixel = 11 * 23 + 11;
+        let variance = |camera: &mut Camera| {
+            let values: Vec<f64> = (0..40)
+                .map(|seed| {
+                    camera.seed = seed;
+                    luminance(&camera.render_buffer(&world)[pixel])
+                })
+                .collect();
+            let mean = values.iter().sum::<f64>() / values.len() as f64;
+            values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64
+        };
+
+        let random = variance(&mut camera);
+        camera.sampler = Sampler::Halton;
+        let halton = variance(&mut camera);
+        assert!(halton < random / 2.0, "{} vs {}", halton, random);
+    }
+
+    #[test]
+    fn buffer_keeps_signed_colors() {
+        let negative = Color::new(-0.5, 2.0, 0.25);
+        let mut world = HittableList::new();
+        world.add(Sphere::at([0., 0., -1.], 0.5).with_material(DiffuseLight::new(negative, 1.)));
+
+        let camera = test_camera();
+        let center = 6 * 23 + 11;
+        let buffer = camera.render_buffer(&world);
+        assert_eq!(buffer[center], negative);
+
+        // clamped only by the writer
+        let mut ppm = Vec::new();
+        let pixel = [buffer[center]];
+        let img = Image::new(1, 1).unwrap();
+        ppm::write(&mut ppm, &img, &pixel, ToneMap::None, 255).unwrap();
+        assert_eq!(String::from_utf8(ppm).unwrap(), "P3\n1 1\n255\n0 254 63 \n");
+    }
+
+    #[test]
+    fn min_hit_distance() {
+        // far from the origin the rounding errors are large
+        let far = 1e5;
+        let mut world = HittableList::new();
+        world.add(Sphere::from_center_radius(far, -1e4 - 0.5, 0., 1e4));
+        // thin gap between the ground and a roof above the contact region
+        world.add(Quad::new(
+            Point3::new(far - 1., -0.45, -1.),
+            Vec3::new(0., 0., 2.),
+            Vec3::new(2., 0., 0.),
+        ));
+
+        let brightness = |camera: &Camera, ray: &Ray| {
+            (0..2000)
+                .map(|i| {
+                    random::seed(i);
+                    luminance(&camera.ray_color(ray, &world, 10))
+                })
+                .sum::<f64>()
+                / 2000.
+        };
+        let open = Ray::new(Point3::new(far, 1., 5.), Vec3::new(0., -1.5, -1.));
+        let contact = Ray::new(Point3::new(far + 2.5, -0.48, 0.), Vec3::new(-1., -0.01, 0.));
+
+        let mut camera = test_camera();
+        let lit = brightness(&camera, &open);
+        let shaded = brightness(&camera, &contact);
+
+        // the ground shadows itself
+        camera.min_hit_distance = 0.0;
+        assert!(brightness(&camera, &open) < 0.5 * lit);
+
+        // the scattered rays skip the roof, more than the gap below it
+        camera.min_hit_distance = 0.1;
+        assert_eq!(brightness(&camera, &open), lit);
+        assert!(brightness(&camera, &contact) > 1.3 * shaded);
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn image_crate_output() {
+        let camera = test_camera();
+        let world = test_world();
+
+        let rgb = camera.render_to_image_crate(&world);
+        assert_eq!(rgb.dimensions(), (23, 13));
+
+        let mut ppm = Vec::new();
+        camera.render(&mut ppm, &world).unwrap();
+        let ppm = String::from_utf8(ppm).unwrap();
+        let row: Vec<u8> = ppm
+            .lines()
+            .nth(3 + 6)
+            .unwrap()
+            .split_whitespace()
+            .map(|v| v.parse().unwrap())
+            .collect();
+        assert_eq!(rgb.get_pixel(11, 6).0, row[3 * 11..3 * 12]);
+    }
+
+    #[test]
+    fn degenerate_ray_background() {
+        let camera = test_camera();
+        let zero = Ray::new(Point3::new(0., 0., 1.), Vec3::zero());
+        let color = camera.ray_color(&zero, &test_world(), 4);
+        assert!(color.components().all(f64::is_finite));
+        assert_eq!(color, Color::new(1., 1., 1.));
+
+        let nan = Ray::new(Point3::zero(), Vec3::new(f64::NAN, 1., 0.));
+        assert_eq!(Camera::background(&nan), Color::new(1., 1., 1.));
+    }
+
+    #[test]
+    fn exposure_and_white_balance() {
+        let mut camera = test_camera();
+        let mid_gray = Color::new(0.18, 0.18, 0.18);
+        assert_eq!(camera.grade(&mid_gray), mid_gray);
+
+        camera.exposure = 2.0;
+        assert_eq!(camera.grade(&mid_gray), Color::new(0.36, 0.36, 0.36));
+        camera.white_balance = Color::new(1., 0.5, 0.25);
+        assert_eq!(camera.grade(&mid_gray), Color::new(0.36, 0.18, 0.09));
+
+        // the doubled values are written, before being clamped
+        camera.white_balance = Color::new(1., 1., 1.);
+        let world = HittableList::new();
+        let exposed = camera.render_output(&world);
+        camera.exposure = 1.0;
+        let neutral = camera.render_output(&world);
+        assert!(exposed.iter().zip(&neutral).all(|(e, n)| *e == 2.0 * *n));
+        assert!(exposed.iter().any(|c| c.x() > 1.0));
+    }
+
+    #[test]
+    fn glossy_mis() {
+        let mut world = HittableList::new();
+        world.add(Ground::new(0., 50.).with_material(Glossy::new(Color::new(0.8, 0.8, 0.8), 0.3)));
+        // small bright panel around the mirror direction of the ray
+        world.add(
+            Quad::new(
+                Point3::new(-0.3, 0.7, -6.),
+                Vec3::new(0.6, 0., 0.),
+                Vec3::new(0., 0.6, 0.),
+            )
+            .with_material(DiffuseLight::new(Color::new(1., 1., 1.), 20.)),
+        );
+
+        let ray = Ray::new(Point3::new(0., 0.5, 3.), Vec3::new(0., -0.5, -3.));
+        let mut camera = test_camera();
+        let (brdf_mean, brdf_variance) = luminance_stats(&camera, &ray, &world, 2, 20000);
+        camera.lights = world.collect_lights();
+        let (mis_mean, mis_variance) = luminance_stats(&camera, &ray, &world, 2, 20000);
+
+        assert!(
+            (mis_mean / brdf_mean - 1.).abs() < 0.05,
+            "{} vs {}",
+            mis_mean,
+            brdf_mean
+        );
+        assert!(
+            mis_variance < brdf_variance / 10.,
+            "{} vs {}",
+            mis_variance,
+            brdf_variance
+        );
+    }
+
+    #[test]
+    fn graded_png_and_frames() {
+        let mut camera = test_camera();
+        let world = test_world();
+        let neutral = camera.render_pixels(&world);
+        camera.exposure = 0.5;
+        camera.white_balance = Color::new(1., 0.5, 1.);
+        let grade = Color::new(0.5, 0.25, 0.5);
+
+        let png = |pixels: &[Pixel]| {
+            let mut out = Vec::new();
+            png::write_rgba(&mut out, &camera.image, pixels, ToneMap::None).unwrap();
+            out
+        };
+        let expected: Vec<Pixel> = neutral
+            .iter()
+            .map(|p| Pixel {
+                color: grade * p.color,
+                alpha: p.alpha,
+            })
+            .collect();
+        assert_eq!(png(&camera.render_pixels(&world)), png(&expected));
+
+        let mut last = Vec::new();
+        camera.render_progressive(&world, |frame, _| last = frame.to_vec());
+        assert_eq!(last, colors(&expected));
+    }
+
+    #[test]
+    fn light_samples_variance() {
+        let mut world = HittableList::new();
+        world.add(Ground::new(0., 50.));
+        world.add(
+            Quad::new(
+                Point3::new(-1., 1., -1.),
+                Vec3::new(2., 0., 0.),
+                Vec3::new(0., 0., 2.),
+            )
+            .with_material(DiffuseLight::one_sided(Color::new(1., 1., 1.), 4.)),
+        );
+        // blocker hiding half of the panel from the lit point
+        world.add(Quad::new(
+            Point3::new(0., 0.5, -2.),
+            Vec3::new(2., 0., 0.),
+            Vec3::new(0., 0., 4.),
+        ));
+
+        let ray = Ray::new(Point3::new(0., 0.5, 3.), Vec3::new(0., -0.5, -3.));
+        // a single bounce, only the direct light
+        let estimate = |camera: &Camera| luminance_stats(camera, &ray, &world, 1, 2000);
+
+        let mut camera = test_camera();
+        camera.lights = world.collect_lights();
+        let (single_mean, single_variance) = estimate(&camera);
+        camera.light_samples = 8;
+        let (mean, variance) = estimate(&camera);
+
+        assert!(single_mean > 0.0);
+        assert!((mean / single_mean - 1.).abs() < 0.1);
+        assert!(
+            variance < single_variance / 4.,
+            "{} vs {}",
+            variance,
+            single_variance
+        );
+    }
+
+    #[test]
+    fn distance_fog() {
+        let mut world = HittableList::new();
+        let red = Lambertian::new(Color::new(1., 0., 0.));
+        world.add(Sphere::at([-1., 0., -2.], 0.5).with_material(red));
+        let red = Lambertian::new(Color::new(1., 0., 0.));
+        world.add(Sphere::at([4., 0., -8.], 0.5).with_material(red));
+
+        let mut camera = test_camera();
+        camera.max_recursion_depth = 1;
+        let fog_color = Color::new(0., 0., 1.);
+        camera.fog = Some((fog_color, 0.2));
+
+        let tint = |target: Point3| {
+            let ray = Ray::new(Point3::zero(), target);
+            let (color, rec) = camera.trace(&ray, &world, 1);
+            assert!(rec.is_some());
+            color.z()
+        };
+        let near = tint(Point3::new(-1., 0., -2.));
+        let far = tint(Point3::new(4., 0., -8.));
+        assert!(near > 0.0);
+        assert!(far > near, "{} vs {}", far, near);
+
+        // the sky is not fogged
+        let up = Ray::new(Point3::zero(), Vec3::new(0., 1., 0.));
+        assert_eq!(camera.ray_color(&up, &world, 1), Camera::background(&up));
+    }
+
+    #[test]
+    fn no_jitter() {
+        let mut camera = test_camera();
+        camera.jitter = false;
+        camera.sample_per_pixel = 8;
+        let ctx = camera.viewport_context();
+
+        let center = camera.get_ray_at(5, 3, &Vec3::zero(), &ctx);
+        for i in 0..8 {
+            assert_eq!(camera.pixel_offset(i, (0.3, 0.7)), Vec3::zero());
+            let ray = camera.get_ray(5, 3, &ctx);
+            assert_eq!(ray.origin(), center.origin());
+            assert_eq!(ray.direction(), center.direction());
+        }
+
+        // the sky alone has no variance left
+        let pixels = camera.render_pixels(&HittableList::new());
+        let expected = Camera::background(&center);
+        assert!(pixels[3 * 23 + 5].color.approx_eq(&expected, 1e-12));
+    }
+
+    #[test]
+    fn cube_map_environment() {
+        let mut camera = test_camera();
+        let world = HittableList::new();
+        let along_x = Ray::new(Point3::zero(), Vec3::new(1., 0., 0.));
+        assert_eq!(
+            camera.ray_color(&along_x, &world, 4),
+            Camera::background(&along_x)
+        );
+
+        // green channel set to the face index
+        let faces = std::array::from_fn(|i| vec![Color::new(0., i as f64, 0.); 4]);
+        camera.environment = Some(Arc::new(CubeMap::new(2, faces).unwrap()));
+        assert_eq!(camera.ray_color(&along_x, &world, 4), Color::zero());
+        let down = Ray::new(Point3::zero(), Vec3::new(0.1, -1., 0.));
+        assert_eq!(camera.ray_color(&down, &world, 4), Color::new(0., 3., 0.));
+    }
+
+    #[test]
+    fn ndc_rays() {
+        let mut camera = test_camera();
+        camera.look_at(
+            Point3::new(1., 2., 3.),
+            Point3::new(0., 0., -1.),
+            Vec3::new(0., 1., 0.),
+        );
+        camera.defocus_radius = 0.5;
+
+        let center = camera.ray_through_ndc(0., 0.);
+        assert_eq!(*center.origin(), camera.look_from());
+        let forward = (camera.target() - camera.look_from()).normal();
+        assert!(center.direction().normal().approx_eq(&forward, 1e-12));
+
+        // the corners of the viewport, as seen by the pixel rays
+        let ctx = camera.viewport_context();
+        let top_left = camera.ray_through_ndc(-1., 1.);
+        let upper_left_corner = *top_left.origin() + *top_left.direction();
+        assert!(upper_left_corner.approx_eq(&camera.upper_left_viewport(), 1e-12));
+        let bottom_right = camera.ray_through_ndc(1., -1.);
+        let corner = ctx.upper_left_pixel + 22.5 * ctx.delta_u + 12.5 * ctx.delta_v;
+        assert!((*bottom_right.origin() + *bottom_right.direction()).approx_eq(&corner, 1e-12));
+    }
+
+    #[test]
+    fn bokeh_keeps_highlights() {
+        let mut world = HittableList::new();
+        let light = DiffuseLight::new(Color::new(1., 1., 1.), 1000.);
+        world.add(Sphere::at([0., 0., -4.], 0.3).with_material(light));
+
+        let mut camera = test_camera();
+        camera.environment = Some(Arc::new(
+            CubeMap::new(1, std::array::from_fn(|_| vec![Color::zero()])).unwrap(),
+        ));
+        camera.sample_per_pixel = 256;
+        let neighbor = |camera: &Camera| camera.render_pixels(&world)[6 * 23 + 12].color.x();
+        let sharp = neighbor(&camera);
+
+        // the highlight spread by the lens is still brighter than white
+        camera.defocus_radius = 0.3;
+        let blurred = neighbor(&camera);
+        assert!(blurred > 1.0, "{}", blurred);
+        assert!(blurred > 10.0 * sharp, "{} vs {}", blurred, sharp);
+
+        // as opposed to clamping every sample before the blur
+        camera.max_sample_luminance = Some(1.0);
+        assert!(neighbor(&camera) < 1.0);
+    }
+
+    #[test]
+    fn depth_buffer() {
+        let mut world = HittableList::new();
+        world.add(Sphere::at([-1., 0., -2.], 0.5));
+        world.add(Sphere::at([1.5, 0., -4.], 0.5));
+
+        let mut camera = test_camera();
+        let gray = |depth: &[Color], u: i32| depth[(6 * 23 + u) as usize].x();
+        let depth = camera.render_depth(&world, 1., 5.);
+        // pixels through the sphere centers and the sky
+        let (near, far, sky) = (gray(&depth, 8), gray(&depth, 14), gray(&depth, 22));
+        assert!(near > far && far > sky, "{} {} {}", near, far, sky);
+        assert_eq!(sky, 0.0);
+        // the front of the near sphere is about 1.5 away
+        assert!((near - 0.875).abs() < 0.03, "{}", near);
+
+        camera.depth_shading = DepthShading::NearBlack;
+        let depth = camera.render_depth(&world, 1., 5.);
+        assert!(gray(&depth, 8) < gray(&depth, 14));
+        assert_eq!(gray(&depth, 22), 1.0);
+
+        // empty range, everything at the near level instead of NaN
+        let depth = camera.render_depth(&world, 2., 2.);
+        assert!(depth.iter().all(|c| *c == Color::zero()));
     }
 }
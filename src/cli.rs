@@ -0,0 +1,138 @@
+//! Command line options of the renderer
+
+use crate::camera::Camera;
+use crate::image::Image;
+
+use std::path::PathBuf;
+
+pub const USAGE: &str = "Usage: rust-tracer [--width N] [--aspect W:H] [--samples N] \
+                         [--depth N] [--stats] [-o PATH]";
+
+/// Settings of the render that can be changed without recompiling
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    /// Width of the image in pixels
+    pub width: i32,
+    /// Ratio between width and height, the height is derived from it
+    pub aspect: f32,
+    /// See [`Camera::sample_per_pixel`]
+    pub samples: i16,
+    /// See [`Camera::max_recursion_depth`]
+    pub depth: i16,
+    /// PPM file the image is written to, standard output if missing
+    pub output: Option<PathBuf>,
+    /// Prints a summary of the scene before rendering, see
+    /// [`crate::scene::SceneStats`]
+    pub stats: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            width: 400,
+            aspect: 16.0 / 9.0,
+            samples: 100,
+            depth: 10,
+            output: None,
+            stats: false,
+        }
+    }
+}
+
+impl Options {
+    /// Parses the arguments following the program name, unspecified options
+    /// keep their default value
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut options = Options::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("Missing value for {}", arg))
+            };
+            match arg.as_str() {
+                "--width" | "-w" => options.width = number(&arg, &value()?)?,
+                "--aspect" => options.aspect = aspect(&value()?)?,
+                "--samples" | "-s" => options.samples = number(&arg, &value()?)?,
+                "--depth" | "-d" => options.depth = number(&arg, &value()?)?,
+                "--output" | "-o" => options.output = Some(PathBuf::from(value()?)),
+                "--stats" => options.stats = true,
+                other => return Err(format!("Unknown argument \"{}\"", other)),
+            }
+        }
+        Ok(options)
+    }
+
+    /// Camera rendering the image described by the options, with the
+    /// viewport of the default scene
+    pub fn camera(&self) -> Result<Camera, String> {
+        let img = Image::from_aspect_ratio(self.width, self.aspect)?;
+
+        let mut camera = Camera::new(img);
+        camera.focal_length = 1.0;
+        camera.set_viewport_from_height(2.0);
+        camera.sample_per_pixel = self.samples;
+        camera.max_recursion_depth = self.depth;
+        Ok(camera)
+    }
+}
+
+fn number<T: std::str::FromStr>(option: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value \"{}\" for {}", value, option))
+}
+
+/// Parses an aspect ratio written either as a number or as `W:H`
+fn aspect(value: &str) -> Result<f32, String> {
+    let ratio = match value.split_once(':') {
+        Some((w, h)) => number::<f32>("--aspect", w)? / number::<f32>("--aspect", h)?,
+        None => number("--aspect", value)?,
+    };
+    // written so that NaN fails the check too
+    if ratio > 0.0 && ratio.is_finite() {
+        Ok(ratio)
+    } else {
+        Err(format!("Invalid value \"{}\" for --aspect", value))
+    }
+}
+
+#[cfg(test)]
+mod cli_tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn parse_args() {
+        let options = Options::parse(args("--width 800 --samples 200 -o out.ppm")).unwrap();
+        assert_eq!(
+            options,
+            Options {
+                width: 800,
+                samples: 200,
+                output: Some(PathBuf::from("out.ppm")),
+                ..Options::default()
+            }
+        );
+
+        let camera = options.camera().unwrap();
+        assert_eq!(camera.image, Image::new(800, 450).unwrap());
+        assert_eq!(camera.sample_per_pixel, 200);
+        assert_eq!(camera.max_recursion_depth, 10);
+
+        let options = Options::parse(args("--aspect 2:1 --depth 4 --stats")).unwrap();
+        assert!(options.stats);
+        let camera = options.camera().unwrap();
+        assert_eq!(camera.image, Image::new(400, 200).unwrap());
+        assert_eq!(camera.max_recursion_depth, 4);
+
+        assert_eq!(Options::parse(args("")).unwrap(), Options::default());
+        assert!(Options::parse(args("--width")).is_err());
+        assert!(Options::parse(args("--width wide")).is_err());
+        assert!(Options::parse(args("--aspect 1:0")).is_err());
+        assert!(Options::parse(args("--fast")).is_err());
+    }
+}
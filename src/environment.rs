@@ -0,0 +1,203 @@
+//! Environment maps giving the color of the rays that escape the scene
+
+use crate::image::{Color, Image};
+use crate::math::Vec3;
+
+/// Face of a [`CubeMap`], in the order of [`CubeMap::new`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CubeFace {
+    PosX,
+    NegX,
+    PosY,
+    NegY,
+    PosZ,
+    NegZ,
+}
+
+impl CubeFace {
+    pub const ALL: [CubeFace; 6] = [
+        CubeFace::PosX,
+        CubeFace::NegX,
+        CubeFace::PosY,
+        CubeFace::NegY,
+        CubeFace::PosZ,
+        CubeFace::NegZ,
+    ];
+
+    /// Column and row of the face in the horizontal cross layout, 4 faces
+    /// wide and 3 faces tall:
+    /// ```text
+    ///        +Y
+    ///    -X  +Z  +X  -Z
+    ///        -Y
+    /// ```
+    fn cross_position(&self) -> (i32, i32) {
+        match self {
+            CubeFace::PosX => (2, 1),
+            CubeFace::NegX => (0, 1),
+            CubeFace::PosY => (1, 0),
+            CubeFace::NegY => (1, 2),
+            CubeFace::PosZ => (1, 1),
+            CubeFace::NegZ => (3, 1),
+        }
+    }
+}
+
+/// Environment made of six square images on the faces of a cube around the
+/// scene, the face seen by a direction is the one of its dominant axis.
+/// Unlike an equirectangular map the texels keep about the same size all
+/// around, so the poles stay sharp. The faces are oriented like the cube
+/// maps of OpenGL, seen from inside the cube.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CubeMap {
+    /// Width and height of every face in pixels
+    size: i32,
+    faces: [Vec<Color>; 6],
+}
+
+impl CubeMap {
+    /// Cube map from the row-major buffers of the faces +X, -X, +Y, -Y, +Z
+    /// and -Z, each `size * size` pixels
+    pub fn new(size: i32, faces: [Vec<Color>; 6]) -> Result<Self, String> {
+        if size < 1 {
+            return Err(format!("Invalid cube map face size {}", size));
+        }
+        for (face, buffer) in CubeFace::ALL.iter().zip(&faces) {
+            if buffer.len() != (size * size) as usize {
+                return Err(format!(
+                    "Face {:?} has {} pixels instead of {}",
+                    face,
+                    buffer.len(),
+                    size * size
+                ));
+            }
+        }
+        Ok(Self { size, faces })
+    }
+
+    /// Cube map from a single image with the faces in the horizontal cross
+    /// layout, see [`CubeFace`]
+    pub fn from_cross(img: &Image, buffer: &[Color]) -> Result<Self, String> {
+        let size = img.width / 4;
+        if img.width != 4 * size || img.height != 3 * size {
+            return Err(format!(
+                "A cross layout is 4 by 3 square faces, got {}x{}",
+                img.width, img.height
+            ));
+        }
+        if buffer.len() != (img.width * img.height) as usize {
+            return Err(format!(
+                "Buffer of {} pixels for a {}x{} image",
+                buffer.len(),
+                img.width,
+                img.height
+            ));
+        }
+
+        let faces = CubeFace::ALL.map(|face| {
+            let (column, row) = face.cross_position();
+            (0..size)
+                .flat_map(|y| {
+                    let start = ((row * size + y) * img.width + column * size) as usize;
+                    buffer[start..start + size as usize].iter().copied()
+                })
+                .collect()
+        });
+        Self::new(size, faces)
+    }
+
+    /// Face seen along `direction` and the coordinates in `[0, 1]` of the
+    /// point seen on it, `(0, 0)` being the top left corner of the face
+    pub fn face_uv(direction: &Vec3) -> (CubeFace, f64, f64) {
+        let (x, y, z) = (direction.x(), direction.y(), direction.z());
+        let (ax, ay, az) = (x.abs(), y.abs(), z.abs());
+        let (face, s, t, major) = if ax >= ay && ax >= az {
+            if x > 0.0 {
+                (CubeFace::PosX, -z, -y, ax)
+            } else {
+                (CubeFace::NegX, z, -y, ax)
+            }
+        } else if ay >= az {
+            if y > 0.0 {
+                (CubeFace::PosY, x, z, ay)
+            } else {
+                (CubeFace::NegY, x, -z, ay)
+            }
+        } else if z > 0.0 {
+            (CubeFace::PosZ, x, -y, az)
+        } else {
+            (CubeFace::NegZ, -x, -y, az)
+        };
+        (face, 0.5 * (s / major + 1.0), 0.5 * (t / major + 1.0))
+    }
+
+    /// Color of the pixel seen along `direction`, which doesn't need to be
+    /// normalized
+    pub fn sample(&self, direction: &Vec3) -> Color {
+        let (face, u, v) = CubeMap::face_uv(direction);
+        // NaN coordinates of degenerate directions end up on the corner
+        let texel = |c: f64| ((c * self.size as f64) as i32).clamp(0, self.size - 1);
+        let buffer = &self.faces[face as usize];
+        buffer[(texel(v) * self.size + texel(u)) as usize]
+    }
+}
+
+#[cfg(test)]
+mod environment_tests {
+    use super::*;
+
+    /// Faces of a single color each, red channel set to the face index, with
+    /// the center of +X in cyan
+    fn faces(size: i32) -> [Vec<Color>; 6] {
+        let mut faces = CubeFace::ALL
+            .map(|face| vec![Color::new(face as usize as f64, 0., 0.); (size * size) as usize]);
+        faces[0][(size * size / 2) as usize] = Color::new(0., 1., 1.);
+        faces
+    }
+
+    #[test]
+    fn face_selection() {
+        let cube = CubeMap::new(3, faces(3)).unwrap();
+        assert_eq!(cube.sample(&Vec3::new(2., 0., 0.)), Color::new(0., 1., 1.));
+        assert_eq!(cube.sample(&Vec3::new(2., 0.9, 0.)), Color::new(0., 0., 0.));
+        assert_eq!(
+            CubeMap::face_uv(&Vec3::new(1., 0., 0.)),
+            (CubeFace::PosX, 0.5, 0.5)
+        );
+
+        let axes = [
+            Vec3::new(1., 0.1, 0.2),
+            Vec3::new(-1., 0.1, 0.2),
+            Vec3::new(0.1, 1., 0.2),
+            Vec3::new(0.1, -1., 0.2),
+            Vec3::new(0.1, 0.2, 1.),
+            Vec3::new(0.1, 0.2, -1.),
+        ];
+        for (face, axis) in CubeFace::ALL.iter().zip(&axes) {
+            assert_eq!(CubeMap::face_uv(axis).0, *face);
+            assert_eq!(cube.sample(axis).x(), *face as usize as f64);
+        }
+
+        // looking up, the top of the image is toward -z
+        let (_, _, v) = CubeMap::face_uv(&Vec3::new(0., 1., -0.5));
+        assert!(v < 0.5);
+        assert!(CubeMap::new(3, faces(2)).is_err());
+    }
+
+    #[test]
+    fn cross_layout() {
+        let img = Image::new(12, 9).unwrap();
+        // every pixel tagged with the column and row of its face
+        let buffer: Vec<Color> = (0..9)
+            .flat_map(|y| (0..12).map(move |x| Color::new((x / 3) as f64, (y / 3) as f64, 0.)))
+            .collect();
+        let cube = CubeMap::from_cross(&img, &buffer).unwrap();
+        for face in CubeFace::ALL {
+            let (column, row) = face.cross_position();
+            let tag = Color::new(column as f64, row as f64, 0.);
+            assert!(cube.faces[face as usize].iter().all(|c| *c == tag));
+        }
+
+        assert!(CubeMap::from_cross(&Image::new(12, 8).unwrap(), &buffer).is_err());
+    }
+}
@@ -0,0 +1,50 @@
+use crate::math::Vec3;
+
+/// Reconstruction filter used to weight the samples of a pixel by their
+/// offset from the pixel center
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum PixelFilter {
+    /// Every sample has the same weight
+    #[default]
+    Box,
+    /// Weight decreasing linearly with the distance from the center, along
+    /// each axis, reaching 0 one pixel away
+    Tent,
+    /// Gaussian with a standard deviation of half a pixel
+    Gaussian,
+}
+
+impl PixelFilter {
+    /// Weight of a sample taken at `offset` from the pixel center, only the
+    /// x and y components are used
+    pub fn weight(&self, offset: &Vec3) -> f64 {
+        let (x, y) = (offset.x(), offset.y());
+        match self {
+            PixelFilter::Box => 1.0,
+            PixelFilter::Tent => (1.0 - x.abs()).max(0.0) * (1.0 - y.abs()).max(0.0),
+            PixelFilter::Gaussian => {
+                const SIGMA: f64 = 0.5;
+                (-(x * x + y * y) / (2.0 * SIGMA * SIGMA)).exp()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod filter_tests {
+    use super::*;
+
+    #[test]
+    fn weights() {
+        let center = Vec3::zero();
+        let edge = Vec3::new(0.5, 0.5, 0.);
+
+        assert_eq!(
+            PixelFilter::Box.weight(&center),
+            PixelFilter::Box.weight(&edge)
+        );
+        assert!(PixelFilter::Tent.weight(&center) > PixelFilter::Tent.weight(&edge));
+        assert!(PixelFilter::Gaussian.weight(&center) > PixelFilter::Gaussian.weight(&edge));
+        assert_eq!(PixelFilter::Gaussian.weight(&center), 1.0);
+    }
+}
@@ -1,8 +1,13 @@
+use crate::aabb::Aabb;
 use crate::interval::Interval;
+use crate::light::Light;
+use crate::material::Material;
 use crate::math::{Point3, Vec3};
 use crate::ray::Ray;
 
-#[derive(Debug)]
+use std::sync::{Arc, OnceLock};
+
+#[derive(Debug, Clone)]
 pub struct HitRecord {
     /// Point hitted by the ray
     pub point: Point3,
@@ -10,21 +15,71 @@ pub struct HitRecord {
     pub normal: Vec3,
     /// Parametrization of the surface hitted by the ray
     pub t: f64,
+    /// Material of the surface hitted by the ray
+    pub material: Arc<dyn Material>,
 
     /// This will be computed calling set_face_normal
     pub front_face: Option<bool>,
+
+    /// Id of the object hit, set by [`Tagged`], 0 for untagged objects
+    pub object_id: u32,
+
+    /// Surface coordinates of the hit point in `[0, 1]`, for the textures
+    pub u: f64,
+    pub v: f64,
+    /// Unit direction along which `u` grows on the surface, perpendicular to
+    /// the normal, zero when the object doesn't define it
+    pub tangent: Vec3,
+    /// Normal crossed with the tangent, so that the tangent, the bitangent
+    /// and the normal make a right-handed frame, zero without tangent
+    pub bitangent: Vec3,
 }
 
 impl HitRecord {
-    pub fn new(point: Point3, outward_normal: Vec3, t: f64, ray: &Ray) -> Self {
+    pub fn new(
+        point: Point3,
+        outward_normal: Vec3,
+        t: f64,
+        ray: &Ray,
+        material: Arc<dyn Material>,
+    ) -> Self {
         let mut rec = HitRecord {
             point,
             normal: outward_normal,
             t,
+            material,
             front_face: None,
+            object_id: 0,
+            u: 0.0,
+            v: 0.0,
+            tangent: Vec3::zero(),
+            bitangent: Vec3::zero(),
         };
         rec.set_face_normal(ray, &outward_normal);
-        return rec;
+        rec
+    }
+
+    /// Sets the tangent frame from the direction `tangent`, made
+    /// perpendicular to the normal. Left unset if `tangent` is parallel to
+    /// the normal.
+    pub fn set_tangent(&mut self, tangent: &Vec3) {
+        let t = tangent.reject_from(&self.normal);
+        if t.length2() > 1e-24 {
+            self.tangent = t.normal();
+            self.bitangent = self.normal.cross(&self.tangent);
+        }
+    }
+
+    /// Panics unless the tangent, the bitangent and the normal make a
+    /// right-handed orthonormal frame
+    #[cfg(test)]
+    pub(crate) fn assert_tangent_frame(&self) {
+        let (t, b, n) = (self.tangent, self.bitangent, self.normal);
+        assert!((t.length() - 1.).abs() < 1e-12);
+        assert!((b.length() - 1.).abs() < 1e-12);
+        assert!(t.dot(&n).abs() < 1e-12 && b.dot(&n).abs() < 1e-12);
+        assert!(t.dot(&b).abs() < 1e-12);
+        assert!(t.cross(&b).approx_eq(&n, 1e-12));
     }
 
     fn set_face_normal(&mut self, ray: &Ray, outward_normal: &Vec3) {
@@ -38,24 +93,119 @@ impl HitRecord {
     }
 }
 
-pub trait Hittable {
+pub trait Hittable: Send + Sync {
     fn hit(&self, ray: &Ray, bounds: Interval) -> Option<HitRecord>;
+
+    /// Box enclosing the whole object
+    fn bounding_box(&self) -> Aabb;
+
+    /// Checks if the ray hits the object for some `t` in `bounds`, without
+    /// looking for the closest hit. Used for occlusion tests.
+    fn hit_any(&self, ray: &Ray, bounds: Interval) -> bool {
+        self.hit(ray, bounds).is_some()
+    }
+
+    /// Lights sampling the emissive surfaces of the object, for the objects
+    /// that support being sampled
+    fn lights(&self) -> Vec<Arc<dyn Light>> {
+        Vec::new()
+    }
+
+    /// Count of triangles making the object, for the statistics of the
+    /// scenes
+    fn triangle_count(&self) -> usize {
+        0
+    }
+}
+
+/// Object tagged with an id, reported in the [`HitRecord`] of its hits so
+/// that the objects can be told apart, e.g. for object masks
+pub struct Tagged {
+    pub object: Box<dyn Hittable>,
+    pub id: u32,
+}
+
+impl Tagged {
+    pub fn new<T: Hittable + 'static>(object: T, id: u32) -> Self {
+        Self {
+            object: Box::new(object),
+            id,
+        }
+    }
+}
+
+impl Hittable for Tagged {
+    fn hit(&self, ray: &Ray, bounds: Interval) -> Option<HitRecord> {
+        let mut rec = self.object.hit(ray, bounds)?;
+        rec.object_id = self.id;
+        Some(rec)
+    }
+
+    fn hit_any(&self, ray: &Ray, bounds: Interval) -> bool {
+        self.object.hit_any(ray, bounds)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.object.bounding_box()
+    }
+
+    fn lights(&self) -> Vec<Arc<dyn Light>> {
+        self.object.lights()
+    }
+
+    fn triangle_count(&self) -> usize {
+        self.object.triangle_count()
+    }
 }
 
+#[derive(Default)]
 pub struct HittableList {
     objects: Vec<Box<dyn Hittable>>,
 }
 
 impl HittableList {
     pub fn new() -> Self {
-        return Self {
+        Self {
             objects: Vec::new(),
-        };
+        }
     }
 
     pub fn add<T: Hittable + 'static>(&mut self, obj: T) {
         self.objects.push(Box::new(obj));
     }
+
+    /// Adds a whole list as a single object of this list. The group is moved
+    /// into the list, its hits and bounding box are resolved recursively.
+    pub fn add_group(&mut self, group: HittableList) {
+        self.add(group);
+    }
+
+    /// Moves all the objects of `other` at the end of the list
+    pub fn append(&mut self, mut other: HittableList) {
+        self.objects.append(&mut other.objects);
+    }
+
+    pub fn into_objects(self) -> Vec<Box<dyn Hittable>> {
+        self.objects
+    }
+
+    pub fn objects(&self) -> &[Box<dyn Hittable>] {
+        &self.objects
+    }
+
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    /// Collects a light for every emissive object of the list, groups
+    /// included, to be sampled by the camera at every diffuse hit
+    pub fn collect_lights(&self) -> Vec<Arc<dyn Light>> {
+        self.lights()
+    }
 }
 
 impl Hittable for HittableList {
@@ -64,14 +214,223 @@ impl Hittable for HittableList {
         let mut closest = bound.max;
 
         for obj in &self.objects {
-            if let Some(rec) = obj.hit(ray, bound) {
-                if closest > rec.t {
-                    closest = rec.t;
-                    ret = Some(rec);
-                }
+            if let Some(rec) = obj.hit(ray, bound)
+                && closest > rec.t
+            {
+                closest = rec.t;
+                ret = Some(rec);
             }
         }
 
         ret
     }
+
+    fn hit_any(&self, ray: &Ray, bounds: Interval) -> bool {
+        self.objects.iter().any(|obj| obj.hit_any(ray, bounds))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.objects
+            .iter()
+            .fold(Aabb::empty(), |bbox, obj| bbox.union(&obj.bounding_box()))
+    }
+
+    fn lights(&self) -> Vec<Arc<dyn Light>> {
+        self.objects.iter().flat_map(|obj| obj.lights()).collect()
+    }
+
+    fn triangle_count(&self) -> usize {
+        self.objects.iter().map(|obj| obj.triangle_count()).sum()
+    }
+}
+
+/// Node of a scene graph: a group like [`HittableList`] that computes its
+/// bounding box on the first request and keeps it until its children change,
+/// so that building the BVH of a large scene many times stays cheap
+#[derive(Default)]
+pub struct SceneNode {
+    children: Vec<Box<dyn Hittable>>,
+    bbox: OnceLock<Aabb>,
+}
+
+impl SceneNode {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add<T: Hittable + 'static>(&mut self, child: T) {
+        self.children_mut().push(Box::new(child));
+    }
+
+    /// Removes and returns the child at `index`, panics if out of bounds
+    pub fn remove(&mut self, index: usize) -> Box<dyn Hittable> {
+        self.children_mut().remove(index)
+    }
+
+    pub fn children(&self) -> &[Box<dyn Hittable>] {
+        &self.children
+    }
+
+    /// Mutable access to the children, the cached box is dropped as they may
+    /// be changed
+    pub fn children_mut(&mut self) -> &mut Vec<Box<dyn Hittable>> {
+        self.bbox.take();
+        &mut self.children
+    }
+
+    /// Bounding box if already computed since the last change
+    pub fn cached_bounding_box(&self) -> Option<Aabb> {
+        self.bbox.get().copied()
+    }
+}
+
+impl Hittable for SceneNode {
+    fn hit(&self, ray: &Ray, bounds: Interval) -> Option<HitRecord> {
+        if !self.bounding_box().hit(ray, bounds) {
+            return None;
+        }
+
+        let mut closest = bounds;
+        let mut nearest = None;
+        for child in &self.children {
+            if let Some(rec) = child.hit(ray, closest) {
+                closest.max = rec.t;
+                nearest = Some(rec);
+            }
+        }
+        nearest
+    }
+
+    fn hit_any(&self, ray: &Ray, bounds: Interval) -> bool {
+        self.bounding_box().hit(ray, bounds)
+            && self.children.iter().any(|child| child.hit_any(ray, bounds))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        *self.bbox.get_or_init(|| {
+            self.children.iter().fold(Aabb::empty(), |bbox, child| {
+                bbox.union(&child.bounding_box())
+            })
+        })
+    }
+
+    fn lights(&self) -> Vec<Arc<dyn Light>> {
+        self.children
+            .iter()
+            .flat_map(|child| child.lights())
+            .collect()
+    }
+
+    fn triangle_count(&self) -> usize {
+        self.children
+            .iter()
+            .map(|child| child.triangle_count())
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod hittable_tests {
+    use super::*;
+    use crate::image::Color;
+    use crate::material::DiffuseLight;
+    use crate::quad::Quad;
+    use crate::sphere::Sphere;
+
+    #[test]
+    fn collect_lights() {
+        let mut world = HittableList::new();
+        world.add(Sphere::from_center_radius(0., 0., -1., 0.5));
+        world.add(Sphere::from_center_radius(0., -100.5, -1., 100.));
+
+        let mut ceiling = HittableList::new();
+        ceiling.add(
+            Quad::new(
+                Point3::new(-1., 2., -2.),
+                Vec3::new(2., 0., 0.),
+                Vec3::new(0., 0., 2.),
+            )
+            .with_material(DiffuseLight::one_sided(Color::new(1., 1., 1.), 4.)),
+        );
+        world.add_group(ceiling);
+
+        let lights = world.collect_lights();
+        assert_eq!(lights.len(), 1);
+        assert!(lights[0].incident(&Point3::zero()).is_some());
+    }
+
+    #[test]
+    fn nested_groups() {
+        let mut table = HittableList::new();
+        table.add(Sphere::from_center_radius(0., 0., -2., 0.5));
+
+        let mut lamp = HittableList::new();
+        lamp.add(Sphere::from_center_radius(3., 0., -2., 0.5));
+
+        let mut room = HittableList::new();
+        room.add_group(lamp);
+
+        let mut world = HittableList::new();
+        world.add_group(table);
+        world.add_group(room);
+        assert_eq!(world.len(), 2);
+
+        // one level and two levels deep
+        let to_table = Ray::new(Point3::zero(), Vec3::new(0., 0., -1.));
+        let to_lamp = Ray::new(Point3::new(3., 0., 0.), Vec3::new(0., 0., -1.));
+        let away = Ray::new(Point3::new(6., 0., 0.), Vec3::new(0., 0., -1.));
+        assert_eq!(world.hit(&to_table, Interval::positive()).unwrap().t, 1.5);
+        assert_eq!(world.hit(&to_lamp, Interval::positive()).unwrap().t, 1.5);
+        assert!(world.hit(&away, Interval::positive()).is_none());
+
+        assert!(world.hit_any(&to_lamp, Interval::positive()));
+        assert!(!world.hit_any(&away, Interval::positive()));
+        assert!(!world.hit_any(&to_lamp, Interval::new(0., 1.)));
+
+        let bbox = world.bounding_box();
+        assert_eq!(bbox.min(), Point3::new(-0.5, -0.5, -2.5));
+        assert_eq!(bbox.max(), Point3::new(3.5, 0.5, -1.5));
+    }
+
+    #[test]
+    fn append_lists() {
+        let mut geometry = HittableList::new();
+        geometry.add(Sphere::from_center_radius(0., 0., -1., 0.5));
+        geometry.add(Sphere::from_center_radius(0., -100.5, -1., 100.));
+
+        let mut lights = HittableList::new();
+        for x in [-2., 0., 2.] {
+            lights.add(
+                Sphere::from_center_radius(x, 3., -1., 0.2)
+                    .with_material(DiffuseLight::new(Color::new(1., 1., 1.), 4.)),
+            );
+        }
+
+        geometry.append(lights);
+        assert_eq!(geometry.len(), 5);
+        assert_eq!(geometry.bounding_box().max().y(), 3.2);
+    }
+
+    #[test]
+    fn scene_node_box_cache() {
+        let mut node = SceneNode::new();
+        node.add(Sphere::from_center_radius(0., 0., -2., 0.5));
+        assert_eq!(node.cached_bounding_box(), None);
+
+        let bbox = node.bounding_box();
+        assert_eq!(bbox.max(), Point3::new(0.5, 0.5, -1.5));
+        assert_eq!(node.cached_bounding_box(), Some(bbox));
+
+        node.add(Sphere::from_center_radius(3., 0., -2., 0.5));
+        assert_eq!(node.cached_bounding_box(), None);
+        assert_eq!(node.bounding_box().max(), Point3::new(3.5, 0.5, -1.5));
+        assert!(node.cached_bounding_box().is_some());
+
+        let to_second = Ray::new(Point3::new(3., 0., 0.), Vec3::new(0., 0., -1.));
+        assert_eq!(node.hit(&to_second, Interval::positive()).unwrap().t, 1.5);
+
+        node.remove(1);
+        assert_eq!(node.bounding_box(), bbox);
+        assert!(node.hit(&to_second, Interval::positive()).is_none());
+    }
 }
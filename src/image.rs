@@ -1,4 +1,6 @@
-use crate::math::Vec3;
+use crate::camera::ToneMap;
+use crate::interval::Interval;
+use crate::math::{Vec3, lerp};
 
 /// Computes the ideal ratio (without any rounding) between width and height.
 /// The formula is
@@ -14,7 +16,7 @@ pub fn aspect_ratio(width: i32, height: i32) -> i32 {
     ideal_ratio(width, height) as i32
 }
 
-#[derive(Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Image {
     pub width: i32,
     pub height: i32,
@@ -30,8 +32,22 @@ impl Image {
         }
     }
 
+    /// Image `width` pixels wide whose height is derived from
+    /// `aspect_ratio`, fails if the height or the pixel count doesn't fit in
+    /// an `i32`
     pub fn from_aspect_ratio(width: i32, aspect_ratio: f32) -> Result<Self, String> {
-        return Image::new(width, (width as f32 / aspect_ratio) as i32);
+        let height = width as f32 / aspect_ratio;
+        if height.is_nan() || height >= i32::MAX as f32 {
+            return Err(format!(
+                "Height of an image {} pixels wide with aspect ratio {} is out of range",
+                width, aspect_ratio
+            ));
+        }
+        let height = height as i32;
+        if width.checked_mul(height).is_none() {
+            return Err(format!("Image of {}x{} pixels is too large", width, height));
+        }
+        Image::new(width, height)
     }
 
     pub fn ideal_ratio(&self) -> f64 {
@@ -47,7 +63,146 @@ impl Image {
         if self.height < 1 || self.width < 1 {
             return false;
         }
-        return true;
+        true
+    }
+
+    /// Image `width` pixels wide with the same aspect ratio, the height is
+    /// rounded to the nearest pixel. Both are at least 1.
+    pub fn scaled_to_width(&self, width: i32) -> Image {
+        let width = width.max(1);
+        let height = (width as f64 / self.ideal_ratio()).round().max(1.0);
+        Image {
+            width,
+            height: height as i32,
+        }
+    }
+}
+
+/// Box filter resize of the row-major `buffer` of the image `from` to the
+/// size of `to`: every pixel of the result averages the block of pixels of
+/// `from` it covers, at least one. Fails if an image is not valid or if the
+/// buffer doesn't hold all the pixels of `from`.
+pub fn downscale(buffer: &[Color], from: Image, to: Image) -> Result<Vec<Color>, String> {
+    if !from.is_valid() || !to.is_valid() {
+        return Err(format!(
+            "Cannot resize from {}x{} to {}x{}",
+            from.width, from.height, to.width, to.height
+        ));
+    }
+    if buffer.len() != from.width as usize * from.height as usize {
+        return Err(format!(
+            "Buffer of {} pixels for a {}x{} image",
+            buffer.len(),
+            from.width,
+            from.height
+        ));
+    }
+
+    // range of source pixels covered by the pixel `i` of the target
+    let block = |i: i32, from_size: i32, to_size: i32| {
+        let start = (i as i64 * from_size as i64 / to_size as i64) as i32;
+        let end = ((i as i64 + 1) * from_size as i64 / to_size as i64) as i32;
+        start..end.max(start + 1)
+    };
+
+    let mut result = Vec::with_capacity((to.width * to.height) as usize);
+    for v in 0..to.height {
+        let rows = block(v, from.height, to.height);
+        for u in 0..to.width {
+            let columns = block(u, from.width, to.width);
+            let mut sum = Color::zero();
+            let mut count = 0;
+            for y in rows.clone() {
+                for x in columns.clone() {
+                    sum += buffer[(y * from.width + x) as usize];
+                    count += 1;
+                }
+            }
+            result.push(sum / count as f64);
+        }
+    }
+    Ok(result)
+}
+
+/// sRGB decoding of a single component from `[0, 1]` to linear
+pub fn srgb_to_linear(c: f64) -> f64 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// sRGB encoding of a single linear component, inverse of [`srgb_to_linear`]
+pub fn linear_to_srgb(c: f64) -> f64 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+impl Color {
+    /// Parses a `#rrggbb` sRGB color, returning it in linear space
+    pub fn from_hex(s: &str) -> Result<Color, String> {
+        let digits = s
+            .strip_prefix('#')
+            .ok_or_else(|| format!("Color \"{}\" does not start with '#'", s))?;
+        if digits.len() != 6 {
+            return Err(format!("Color \"{}\" must have 6 hex digits", s));
+        }
+
+        let mut c = [0.0; 3];
+        for (i, channel) in c.iter_mut().enumerate() {
+            let byte = digits
+                .get(2 * i..2 * i + 2)
+                .and_then(|d| u8::from_str_radix(d, 16).ok())
+                .ok_or_else(|| format!("Color \"{}\" contains non hex digits", s))?;
+            *channel = srgb_to_linear(byte as f64 / 255.0);
+        }
+
+        Ok(Color::new(c[0], c[1], c[2]))
+    }
+
+    /// Formats the linear color as a `#rrggbb` sRGB string, components are
+    /// clamped to `[0, 1]`
+    pub fn to_hex(&self) -> String {
+        let byte = |v: f64| (255.0 * linear_to_srgb(v.clamp(0.0, 1.0))).round() as u8;
+        format!(
+            "#{:02x}{:02x}{:02x}",
+            byte(self.x()),
+            byte(self.y()),
+            byte(self.z())
+        )
+    }
+
+    /// Tone mapped channels clamped to the range written to the images and
+    /// scaled to `max_value`, as they appear in the output files
+    pub fn to_channels(&self, tone_map: ToneMap, max_value: u16) -> [u16; 3] {
+        let intensity = Interval::intensity();
+        let max = max_value as f64;
+        [self.x(), self.y(), self.z()].map(|c| (max * intensity.clamp(tone_map.apply(c))) as u16)
+    }
+
+    /// 8-bit channels of the color, see [`Color::to_channels`]
+    pub fn to_rgb8(&self, tone_map: ToneMap) -> [u8; 3] {
+        self.to_channels(tone_map, u8::MAX as u16).map(|c| c as u8)
+    }
+
+    /// Composites the color over `bg` with the given opacity,
+    /// `self * alpha + bg * (1 - alpha)`. Additive and multiplicative blends
+    /// are the `+` and `*` operators of [`Color`].
+    pub fn over(&self, bg: &Color, alpha: f64) -> Color {
+        lerp(bg, self, alpha)
+    }
+
+    /// Brings the color in `[0, 1]` scaling all the channels by the same
+    /// factor when the brightest is over 1, so the hue doesn't shift like
+    /// with a per-channel clamp. Negative channels are set to 0.
+    pub fn clamp_preserve_hue(&self) -> Color {
+        let c = Color::new(self.x().max(0.0), self.y().max(0.0), self.z().max(0.0));
+        let max = c.max_component();
+        if max > 1.0 { c / max } else { c }
     }
 }
 
@@ -60,6 +215,102 @@ fn image_constructors() {
     let i2 = Image::from_aspect_ratio(800, 2.0).unwrap();
     assert_eq!(i2.width, 800);
     assert_eq!(i2.height, 400);
+
+    assert_eq!(i1.scaled_to_width(200), Image::new(200, 150).unwrap());
+    assert_eq!(i2.scaled_to_width(1), Image::new(1, 1).unwrap());
+}
+
+#[test]
+fn huge_aspect_ratio_images() {
+    let err = Image::from_aspect_ratio(i32::MAX, 16.0 / 9.0).unwrap_err();
+    assert!(err.contains("too large"), "{}", err);
+    let err = Image::from_aspect_ratio(i32::MAX, 0.5).unwrap_err();
+    assert!(err.contains("out of range"), "{}", err);
+    assert!(Image::from_aspect_ratio(400, 0.0).is_err());
+    assert!(Image::from_aspect_ratio(400, f32::NAN).is_err());
+    assert!(Image::from_aspect_ratio(46340, 1.0).is_ok());
+}
+
+#[test]
+fn box_downscale() {
+    let from = Image::new(4, 4).unwrap();
+    let buffer: Vec<Color> = (0..16).map(|i| Color::new(i as f64, 0., 1.)).collect();
+
+    let to = from.scaled_to_width(2);
+    let thumbnail = downscale(&buffer, from, to).unwrap();
+    // average of the indices of each 2x2 block
+    let expected = [2.5, 4.5, 10.5, 12.5].map(|r| Color::new(r, 0., 1.));
+    assert_eq!(thumbnail, expected);
+
+    assert_eq!(downscale(&buffer, from, from).unwrap(), buffer);
+
+    assert_eq!(from.scaled_to_width(0), Image::new(1, 1).unwrap());
+    assert_eq!(from.scaled_to_width(-5), Image::new(1, 1).unwrap());
+    assert!(downscale(&buffer[1..], from, to).is_err());
+    let empty = Image {
+        width: 0,
+        height: 0,
+    };
+    assert!(downscale(&buffer, from, empty).is_err());
 }
 
 pub type Color = Vec3;
+
+/// Relative luminance of a linear color, using the Rec. 709 weights
+pub fn luminance(c: &Color) -> f64 {
+    0.2126 * c.x() + 0.7152 * c.y() + 0.0722 * c.z()
+}
+
+#[test]
+fn clamp_preserving_hue() {
+    let orange = Color::new(2.0, 1.0, 0.0);
+    assert_eq!(orange.clamp_preserve_hue(), Color::new(1.0, 0.5, 0.0));
+
+    let dim = Color::new(0.5, -0.1, 0.25);
+    assert_eq!(dim.clamp_preserve_hue(), Color::new(0.5, 0.0, 0.25));
+}
+
+#[test]
+fn compositing() {
+    let src = Color::new(1.0, 0.5, 0.0);
+    let bg = Color::new(0.0, 0.5, 1.0);
+    assert_eq!(src.over(&bg, 1.0), src);
+    assert_eq!(src.over(&bg, 0.0), bg);
+    assert_eq!(src.over(&bg, 0.5), Color::new(0.5, 0.5, 0.5));
+
+    assert_eq!(src + bg, Color::new(1.0, 1.0, 1.0));
+    assert_eq!(src * bg, Color::new(0.0, 0.25, 0.0));
+}
+
+#[test]
+fn rgb8_conversion() {
+    let c = Color::new(0.25, 2.0, -1.0);
+    for tone_map in [ToneMap::Gamma2, ToneMap::None] {
+        let mut ppm = Vec::new();
+        crate::camera::ppm::write_color(&mut ppm, &c, tone_map, 255).unwrap();
+        let [r, g, b] = c.to_rgb8(tone_map);
+        assert_eq!(
+            String::from_utf8(ppm).unwrap(),
+            format!("{} {} {} ", r, g, b)
+        );
+    }
+    assert_eq!(c.to_rgb8(ToneMap::Gamma2), [127, 254, 0]);
+    assert_eq!(c.to_channels(ToneMap::None, 65535), [16383, 65469, 0]);
+}
+
+#[test]
+fn hex_colors() {
+    assert_eq!(Color::from_hex("#ffffff").unwrap(), Color::new(1., 1., 1.));
+    assert_eq!(Color::from_hex("#000000").unwrap(), Color::zero());
+    assert_eq!(Color::from_hex("#ffffff").unwrap().to_hex(), "#ffffff");
+    assert_eq!(Color::from_hex("#000000").unwrap().to_hex(), "#000000");
+    assert_eq!(Color::from_hex("#3a7fc2").unwrap().to_hex(), "#3a7fc2");
+
+    // mid gray in sRGB is much darker in linear space
+    assert!((Color::from_hex("#808080").unwrap().x() - 0.2158).abs() < 1e-3);
+
+    assert!(Color::from_hex("ffffff").is_err());
+    assert!(Color::from_hex("#fff").is_err());
+    assert!(Color::from_hex("#gggggg").is_err());
+    assert!(Color::from_hex("#ffé00").is_err());
+}
@@ -1,16 +1,25 @@
 const INF: f64 = f64::MAX;
 
-#[derive(Clone, Copy)] // Copy since < 64 bytes
+/// Upper bound of the color components written by the image writers, just
+/// below 1 so that scaling by 256 never overflows a byte
+const MAX_INTENSITY: f64 = 0.999;
+
+#[derive(Debug, Clone, Copy, PartialEq)] // Copy since < 64 bytes
 pub struct Interval {
     pub min: f64,
     pub max: f64,
 }
 
 impl Interval {
-    pub fn new(min: f64, max: f64) -> Self {
+    pub const fn new(min: f64, max: f64) -> Self {
         Self { min, max }
     }
 
+    /// Range of the color components written to the images
+    pub const fn intensity() -> Self {
+        Self::new(0.0, MAX_INTENSITY)
+    }
+
     pub fn empty() -> Self {
         Self {
             min: INF,
@@ -33,6 +42,14 @@ impl Interval {
         Self { min: -INF, max: 0. }
     }
 
+    /// Smallest interval containing both intervals
+    pub fn union(&self, other: &Interval) -> Self {
+        Self {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
     pub fn clamp(&self, v: f64) -> f64 {
         if v < self.min {
             return self.min;
@@ -55,4 +72,49 @@ impl Interval {
     pub fn surrounds(&self, v: f64) -> bool {
         self.min < v && v < self.max
     }
+
+    /// Point at the fraction `t` of the way from `min` to `max`, `t` outside
+    /// of `[0, 1]` extrapolates
+    pub fn lerp(&self, t: f64) -> f64 {
+        self.min + t * self.size()
+    }
+
+    /// Fraction of the way from `min` to `max` where `v` lies, the inverse of
+    /// [`Interval::lerp`]. Not finite for an interval of size 0.
+    pub fn inv_lerp(&self, v: f64) -> f64 {
+        (v - self.min) / self.size()
+    }
+}
+
+#[cfg(test)]
+mod interval_tests {
+    use super::*;
+
+    #[test]
+    fn equality() {
+        assert_eq!(Interval::new(0.0, 1.0), Interval::new(0.0, 1.0));
+        assert_ne!(Interval::new(0.0, 1.0), Interval::new(0.0, 2.0));
+        assert_eq!(
+            format!("{:?}", Interval::new(0.0, 1.0)),
+            "Interval { min: 0.0, max: 1.0 }"
+        );
+    }
+
+    #[test]
+    fn intensity() {
+        const INTENSITY: Interval = Interval::intensity();
+        assert_eq!(INTENSITY.clamp(1.5), 0.999);
+        assert_eq!(INTENSITY.clamp(-0.5), 0.0);
+        assert_eq!(INTENSITY.clamp(0.25), 0.25);
+    }
+
+    #[test]
+    fn remap() {
+        let i = Interval::new(2.0, 4.0);
+        assert_eq!(i.lerp(0.5), 3.0);
+        assert_eq!(i.inv_lerp(3.0), 0.5);
+        assert_eq!(i.lerp(0.0), 2.0);
+        assert_eq!(i.lerp(1.5), 5.0);
+        assert!((i.inv_lerp(i.lerp(0.3)) - 0.3).abs() < 1e-12);
+    }
 }
@@ -0,0 +1,30 @@
+pub mod aabb;
+pub mod accumulator;
+pub mod bvh;
+pub mod camera;
+pub mod cli;
+pub mod environment;
+pub mod filter;
+pub mod hittable;
+pub mod image;
+pub mod interval;
+pub mod light;
+pub mod material;
+pub mod math;
+pub mod matrix;
+pub mod mesh;
+pub mod overlay;
+pub mod patch;
+pub mod perlin;
+pub mod png;
+pub mod progress;
+pub mod quad;
+pub mod random;
+pub mod ray;
+pub mod sampler;
+pub mod scene;
+pub mod scenes;
+pub mod sphere;
+pub mod stats;
+pub mod texture;
+pub mod transform;
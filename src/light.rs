@@ -0,0 +1,289 @@
+//! Lights sampled explicitly at every diffuse hit (direct lighting)
+
+use crate::hittable::Hittable;
+use crate::image::Color;
+use crate::interval::Interval;
+use crate::material::Material;
+use crate::math::{Point3, UnitVec3, Vec3};
+use crate::quad::Quad;
+use crate::random::normal_random;
+use crate::ray::Ray;
+use crate::sphere::Sphere;
+
+use std::sync::Arc;
+
+/// Light arriving at a point from a light source
+pub struct LightSample {
+    /// Unit vector from the lit point toward the light
+    pub direction: Vec3,
+    /// Distance between the lit point and the light
+    pub distance: f64,
+    /// Radiance reaching the point, before any shadowing, already divided by
+    /// `pdf`
+    pub radiance: Color,
+    /// Probability density of the sampled direction with respect to the
+    /// solid angle, infinite for the lights reduced to a point
+    pub pdf: f64,
+}
+
+pub trait Light: Send + Sync {
+    /// Light reaching `point`, None if the point can't receive any light
+    fn incident(&self, point: &Point3) -> Option<LightSample>;
+
+    /// Material of the scene geometry this light samples, None for lights
+    /// without geometry. Rays bouncing off diffuse surfaces don't add the
+    /// emission of this material again, as it was already sampled directly.
+    fn material(&self) -> Option<&Arc<dyn Material>> {
+        None
+    }
+
+    /// Probability density with respect to the solid angle that `incident`
+    /// samples `direction` from `point`, 0 for the directions missing the
+    /// light and for the lights reduced to a point, that rays can't hit
+    fn pdf(&self, _point: &Point3, _direction: &Vec3) -> f64 {
+        0.0
+    }
+}
+
+/// Point light emitting inside a cone. The light is full inside the inner
+/// angle and fades smoothly to zero at the outer angle.
+pub struct SpotLight {
+    pub position: Point3,
+    /// Axis of the cone
+    pub direction: UnitVec3,
+    cos_inner: f64,
+    cos_outer: f64,
+    pub intensity: Color,
+}
+
+impl SpotLight {
+    /// Angles are in radians, measured from the cone axis
+    pub fn new(
+        position: Point3,
+        direction: Vec3,
+        inner_angle: f64,
+        outer_angle: f64,
+        intensity: Color,
+    ) -> Self {
+        let outer_angle = outer_angle.max(inner_angle);
+        Self {
+            position,
+            direction: UnitVec3::new(direction),
+            cos_inner: inner_angle.cos(),
+            cos_outer: outer_angle.cos(),
+            intensity,
+        }
+    }
+
+    /// Fraction of the intensity emitted toward a direction whose cosine with
+    /// the cone axis is `cos_theta`
+    pub fn falloff(&self, cos_theta: f64) -> f64 {
+        if cos_theta >= self.cos_inner {
+            return 1.0;
+        }
+        if cos_theta <= self.cos_outer {
+            return 0.0;
+        }
+
+        // smoothstep between the outer and the inner cone
+        let t = (cos_theta - self.cos_outer) / (self.cos_inner - self.cos_outer);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+impl Light for SpotLight {
+    fn incident(&self, point: &Point3) -> Option<LightSample> {
+        let to_light = self.position - *point;
+        let distance = to_light.length();
+        if distance <= 0.0 {
+            return None;
+        }
+        let direction = to_light / distance;
+
+        let falloff = self.falloff(-direction.dot(&self.direction));
+        if falloff <= 0.0 {
+            return None;
+        }
+
+        Some(LightSample {
+            direction,
+            distance,
+            radiance: self.intensity * (falloff / (distance * distance)),
+            pdf: f64::INFINITY,
+        })
+    }
+}
+
+/// Emissive [`Quad`] of the scene, sampled at a random point of its surface
+pub struct QuadLight {
+    pub quad: Quad,
+    area: f64,
+}
+
+impl QuadLight {
+    pub fn new(quad: Quad) -> Self {
+        let area = quad.u.cross(&quad.v).length();
+        Self { quad, area }
+    }
+
+    /// Density of the uniform sampling of the area converted to solid angle,
+    /// for the point at `distance` along the unit vector `direction`
+    fn pdf_at(&self, distance: f64, direction: &Vec3) -> f64 {
+        let cos_light = direction.dot(&self.quad.normal()).abs();
+        distance * distance / (self.area * cos_light)
+    }
+}
+
+impl Light for QuadLight {
+    fn incident(&self, point: &Point3) -> Option<LightSample> {
+        let on_light = self.quad.q + normal_random() * self.quad.u + normal_random() * self.quad.v;
+        let to_light = on_light - *point;
+        let distance = to_light.length();
+        if distance <= 0.0 {
+            return None;
+        }
+        let direction = to_light / distance;
+
+        let rec = self
+            .quad
+            .hit(&Ray::new(*point, direction), Interval::positive())?;
+        let emitted = rec.material.emitted(&rec);
+
+        let pdf = self.pdf_at(distance, &direction);
+        Some(LightSample {
+            direction,
+            distance,
+            radiance: emitted / pdf,
+            pdf,
+        })
+    }
+
+    fn pdf(&self, point: &Point3, direction: &Vec3) -> f64 {
+        let direction = direction.normal();
+        match self
+            .quad
+            .hit(&Ray::new(*point, direction), Interval::positive())
+        {
+            Some(rec) => self.pdf_at(rec.t, &direction),
+            None => 0.0,
+        }
+    }
+
+    fn material(&self) -> Option<&Arc<dyn Material>> {
+        Some(&self.quad.material)
+    }
+}
+
+/// Emissive [`Sphere`] of the scene, sampled uniformly in the cone of
+/// directions it subtends
+pub struct SphereLight {
+    pub sphere: Sphere,
+}
+
+impl SphereLight {
+    pub fn new(sphere: Sphere) -> Self {
+        Self { sphere }
+    }
+}
+
+impl Light for SphereLight {
+    fn incident(&self, point: &Point3) -> Option<LightSample> {
+        let direction = self.sphere.random_direction_toward(point);
+        let rec = self
+            .sphere
+            .hit(&Ray::new(*point, direction), Interval::positive())?;
+        let emitted = rec.material.emitted(&rec);
+
+        let pdf = self.sphere.pdf_value(point, &direction);
+        Some(LightSample {
+            direction,
+            distance: rec.t,
+            radiance: emitted / pdf,
+            pdf,
+        })
+    }
+
+    fn material(&self) -> Option<&Arc<dyn Material>> {
+        Some(&self.sphere.material)
+    }
+
+    fn pdf(&self, point: &Point3, direction: &Vec3) -> f64 {
+        self.sphere.pdf_value(point, direction)
+    }
+}
+
+#[cfg(test)]
+mod light_tests {
+    use super::*;
+    use crate::material::DiffuseLight;
+
+    #[test]
+    fn spot_cone() {
+        let spot = SpotLight::new(
+            Point3::new(0., 2., 0.),
+            Vec3::new(0., -1., 0.),
+            20f64.to_radians(),
+            30f64.to_radians(),
+            Color::new(4., 4., 4.),
+        );
+
+        // on the axis: full intensity with inverse square falloff
+        let on_axis = spot.incident(&Point3::zero()).unwrap();
+        assert_eq!(on_axis.direction, Vec3::new(0., 1., 0.));
+        assert_eq!(on_axis.distance, 2.0);
+        assert_eq!(on_axis.radiance, Color::new(1., 1., 1.));
+
+        // 45 degrees away from the axis, past the outer cone
+        assert!(spot.incident(&Point3::new(2., 0., 0.)).is_none());
+
+        // between the inner and outer cone the light fades
+        let between = spot.falloff(25f64.to_radians().cos());
+        assert!(between > 0.0 && between < 1.0);
+    }
+
+    #[test]
+    fn quad_light_radiance() {
+        let white = Color::new(1., 1., 1.);
+        // 0.2 x 0.2 panel facing down, far enough to be almost a point light
+        let quad = Quad::new(
+            Point3::new(-0.1, 10., -0.1),
+            Vec3::new(0.2, 0., 0.),
+            Vec3::new(0., 0., 0.2),
+        )
+        .with_material(DiffuseLight::one_sided(white, 100.));
+        let light = QuadLight::new(quad);
+
+        let sample = light.incident(&Point3::zero()).unwrap();
+        assert!(sample.direction.approx_eq(&Vec3::new(0., 1., 0.), 1e-2));
+        assert!((sample.distance - 10.).abs() < 1e-3);
+        assert!((sample.radiance.x() - 100. * 0.04 / 100.).abs() < 1e-4);
+        let pdf = light.pdf(&Point3::zero(), &Vec3::new(0., 1., 0.));
+        assert!((pdf - 100. / 0.04).abs() < 1e-6);
+        assert!((sample.pdf / pdf - 1.).abs() < 1e-3);
+        assert_eq!(light.pdf(&Point3::zero(), &Vec3::new(1., 0., 0.)), 0.0);
+
+        // the back of a one-sided light is dark
+        let above = light.incident(&Point3::new(0., 20., 0.)).unwrap();
+        assert_eq!(above.radiance, Color::zero());
+    }
+
+    #[test]
+    fn sphere_light_radiance() {
+        let white = Color::new(1., 1., 1.);
+        let sphere = Sphere::new(Point3::new(0., 10., 0.), 0.5)
+            .with_material(DiffuseLight::new(white, 100.));
+        let light = SphereLight::new(sphere.clone());
+        assert_eq!(sphere.lights().len(), 1);
+        assert!(Sphere::new(Point3::zero(), 1.).lights().is_empty());
+
+        // the radiance divided by the pdf is the emission times the solid
+        // angle, for every sample
+        let solid_angle = light.sphere.solid_angle(&Point3::zero());
+        for _ in 0..100 {
+            let sample = light.incident(&Point3::zero()).unwrap();
+            assert!(sample.distance > 9.4 && sample.distance < 10.);
+            assert!((sample.radiance.x() - 100. * solid_angle).abs() < 1e-9);
+            assert_eq!(sample.pdf, light.pdf(&Point3::zero(), &sample.direction));
+        }
+    }
+}
@@ -1,34 +1,42 @@
-mod camera;
-mod hittable;
-mod image;
-mod interval;
-mod math;
-mod random;
-mod ray;
-mod sphere;
+use rust_tracer::cli::{self, Options};
+use rust_tracer::hittable::HittableList;
+use rust_tracer::scene::SceneStats;
+use rust_tracer::sphere::Sphere;
 
-use camera::Camera;
-use hittable::HittableList;
-use image::Image;
-use sphere::Sphere;
+use std::sync::Arc;
 
 // //////////////////////////////////////////////////////
 // Entry point
 // //////////////////////////////////////////////////////
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let img = Image::from_aspect_ratio(400, 16.0 / 9.0)?;
-
-    let mut camera = Camera::new(img);
-
-    camera.focal_length = 1.0;
-    camera.set_viewport_from_height(2.0);
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n{}", e, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+    let mut camera = options.camera()?;
+    camera.progress = Some(Arc::new(|report| {
+        if report.finished {
+            eprintln!("\r{report}");
+        } else {
+            eprint!("\r{report}");
+        }
+    }));
 
     let mut world = HittableList::new();
     world.add(Sphere::from_center_radius(0., 0., -1., 0.5));
     world.add(Sphere::from_center_radius(0., -100.5, -1., 100.0));
-
-    camera.render(&mut std::io::stdout(), &world)?;
+    if options.stats {
+        eprintln!("{}", SceneStats::of(&world));
+    }
+
+    match &options.output {
+        Some(path) => camera.render_to_file(&world, path)?,
+        None => camera.render(&mut std::io::stdout(), &world)?,
+    }
 
     eprintln!("finished");
 
@@ -0,0 +1,922 @@
+//! Materials define how the surfaces scatter and emit light
+
+use crate::hittable::HitRecord;
+use crate::image::Color;
+use crate::math::{Vec3, reflectance};
+use crate::random::normal_random;
+use crate::ray::Ray;
+use crate::sampler;
+use crate::texture::{SolidColor, Texture};
+
+use std::f64::consts::PI;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+/// Ray bounced off a surface
+pub struct ScatterRecord {
+    /// Fraction of the light of the scattered ray that gets back along the
+    /// incoming ray, per channel
+    pub attenuation: Color,
+    pub scattered: Ray,
+}
+
+pub trait Material: Send + Sync + Debug {
+    /// Bounces the incoming ray off the surface, None if the ray is absorbed
+    fn scatter(&self, _ray_in: &Ray, _rec: &HitRecord) -> Option<ScatterRecord> {
+        None
+    }
+
+    /// Light emitted by the surface at the hit point
+    fn emitted(&self, _rec: &HitRecord) -> Color {
+        Color::zero()
+    }
+
+    /// Checks if the material emits light, so that the surfaces made of it
+    /// are sampled as lights
+    fn is_emissive(&self) -> bool {
+        false
+    }
+
+    /// Albedo of the diffuse part of the surface, used to reflect the light
+    /// coming straight from the lights
+    fn diffuse_albedo(&self, _rec: &HitRecord) -> Color {
+        Color::zero()
+    }
+
+    /// Attenuation and density with respect to the solid angle of the glossy
+    /// lobe that [`Material::scatter`] samples exactly, scattering the ray
+    /// `ray_in` along `direction`, so that the light coming from `direction`
+    /// can be sampled directly: the reflected fraction is the attenuation
+    /// times the density. None for the materials without such a lobe.
+    fn glossy_lobe(
+        &self,
+        _ray_in: &Ray,
+        _rec: &HitRecord,
+        _direction: &Vec3,
+    ) -> Option<(Color, f64)> {
+        None
+    }
+
+    /// Base color of the surface at the hit point before any lighting, for
+    /// the albedo guide buffer of denoisers. The diffuse albedo by default.
+    fn base_color(&self, rec: &HitRecord) -> Color {
+        self.diffuse_albedo(rec)
+    }
+
+    /// Same as [`Material::scatter`] with the diffuse reflection sampled
+    /// according to `mode`, for the materials with a diffuse part
+    fn scatter_with_mode(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        _mode: DiffuseMode,
+    ) -> Option<ScatterRecord> {
+        self.scatter(ray_in, rec)
+    }
+
+    /// Normal used to shade the hit point, it replaces the geometric normal
+    /// of the record before the other methods are called
+    fn shading_normal(&self, rec: &HitRecord) -> Vec3 {
+        rec.normal
+    }
+}
+
+/// How the direction of a diffuse bounce is chosen
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum DiffuseMode {
+    /// Normal plus a random unit vector, which gives a cosine distribution
+    #[default]
+    SphereOffset,
+    /// Uniform over the hemisphere around the normal with the albedo as
+    /// attenuation, the simpler model of the book: it is not Lambertian and
+    /// looks flatter
+    Hemisphere,
+    /// Cosine distribution sampled from a uniform point of the unit disk
+    /// projected on the hemisphere (Malley's method)
+    Cosine,
+}
+
+impl DiffuseMode {
+    /// Random bounce direction off a surface with unit normal `normal`
+    pub fn sample(&self, normal: &Vec3) -> Vec3 {
+        match self {
+            DiffuseMode::SphereOffset => {
+                let direction = *normal + sampler::next_on_sphere();
+                // the random vector can be almost opposite to the normal
+                if direction.length2() < 1e-16 {
+                    *normal
+                } else {
+                    direction
+                }
+            }
+            DiffuseMode::Hemisphere => {
+                let direction = sampler::next_on_sphere();
+                if normal.dot(&direction) > 0.0 {
+                    direction
+                } else {
+                    -direction
+                }
+            }
+            DiffuseMode::Cosine => {
+                let (s, t) = sampler::next_2d();
+                let r = s.sqrt();
+                let phi = 2.0 * PI * t;
+                let (t, b) = tangent_frame(normal, &any_tangent(normal));
+                let z = (1.0 - r * r).max(0.0).sqrt();
+                r * phi.cos() * t + r * phi.sin() * b + z * *normal
+            }
+        }
+    }
+
+    /// Probability density with respect to the solid angle that `sample`
+    /// returns `direction` around the unit normal `normal`
+    pub fn pdf(&self, normal: &Vec3, direction: &Vec3) -> f64 {
+        let cosine = normal.dot(&direction.normal());
+        if cosine <= 0.0 {
+            return 0.0;
+        }
+        match self {
+            DiffuseMode::Hemisphere => 1.0 / (2.0 * PI),
+            DiffuseMode::SphereOffset | DiffuseMode::Cosine => cosine / PI,
+        }
+    }
+}
+
+/// Some unit vector not parallel to `n`
+fn any_tangent(n: &Vec3) -> Vec3 {
+    if n.x().abs() < 0.9 {
+        Vec3::new(1., 0., 0.)
+    } else {
+        Vec3::new(0., 1., 0.)
+    }
+}
+
+/// Unit tangent and bitangent perpendicular to the unit normal `n`, the
+/// tangent being the part of `tangent` perpendicular to `n`
+fn tangent_frame(n: &Vec3, tangent: &Vec3) -> (Vec3, Vec3) {
+    let t = tangent.reject_from(n).normal();
+    (t, n.cross(&t))
+}
+
+/// Tangent frame around the unit normal `n` following `tangent`, or any
+/// frame when the surface has no tangent
+fn surface_frame(n: &Vec3, tangent: &Vec3) -> (Vec3, Vec3) {
+    if tangent.cross(n).length2() > 1e-12 {
+        tangent_frame(n, tangent)
+    } else {
+        tangent_frame(n, &any_tangent(n))
+    }
+}
+
+/// Ideal diffuse surface
+#[derive(Debug)]
+pub struct Lambertian {
+    pub albedo: Arc<dyn Texture>,
+}
+
+impl Lambertian {
+    pub fn new(albedo: Color) -> Self {
+        Self::textured(SolidColor::new(albedo))
+    }
+
+    /// Diffuse surface whose albedo varies according to a texture
+    pub fn textured<T: Texture + 'static>(texture: T) -> Self {
+        Self {
+            albedo: Arc::new(texture),
+        }
+    }
+}
+
+impl Material for Lambertian {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        self.scatter_with_mode(ray_in, rec, DiffuseMode::default())
+    }
+
+    fn scatter_with_mode(
+        &self,
+        _ray_in: &Ray,
+        rec: &HitRecord,
+        mode: DiffuseMode,
+    ) -> Option<ScatterRecord> {
+        let direction = mode.sample(&rec.normal);
+        Some(ScatterRecord {
+            attenuation: self.diffuse_albedo(rec),
+            scattered: Ray::new(rec.point, direction),
+        })
+    }
+
+    fn diffuse_albedo(&self, rec: &HitRecord) -> Color {
+        self.albedo.value(rec.u, rec.v, &rec.point)
+    }
+}
+
+/// Mirror direction of `v` about the surface with unit normal `n`
+fn reflect(v: &Vec3, n: &Vec3) -> Vec3 {
+    *v - 2.0 * v.dot(n) * *n
+}
+
+/// Direction of the unit vector `v` refracted through the surface with unit
+/// normal `n`, `eta` being the ratio of the refraction indices (Snell's law)
+fn refract(v: &Vec3, n: &Vec3, eta: f64) -> Vec3 {
+    let cos_theta = (-*v).dot(n).min(1.0);
+    let perpendicular = eta * (*v + cos_theta * *n);
+    let parallel = -(1.0 - perpendicular.length2()).abs().sqrt() * *n;
+    perpendicular + parallel
+}
+
+/// Split of the light hitting a smooth interface between the reflected and
+/// the refracted directions, for the materials with a clear coat or made of
+/// a dielectric
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FresnelSplit {
+    pub reflected: Vec3,
+    /// None when the light is totally reflected
+    pub refracted: Option<Vec3>,
+    /// Fraction of the light reflected, the rest being refracted
+    pub reflectance: f64,
+}
+
+impl FresnelSplit {
+    /// Split of the light coming along `direction` on the surface with unit
+    /// normal `normal` facing it, `eta` being the ratio of the refraction
+    /// indices
+    pub fn new(direction: &Vec3, normal: &Vec3, eta: f64) -> Self {
+        let unit_direction = direction.normal();
+        let cos_theta = (-unit_direction).dot(normal).min(1.0);
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+
+        let reflected = reflect(&unit_direction, normal);
+        if eta * sin_theta > 1.0 {
+            return Self {
+                reflected,
+                refracted: None,
+                reflectance: 1.0,
+            };
+        }
+        Self {
+            reflected,
+            refracted: Some(refract(&unit_direction, normal, eta)),
+            reflectance: reflectance(cos_theta, eta),
+        }
+    }
+
+    /// Picks the reflected direction with a probability equal to the
+    /// reflectance, the refracted one otherwise, so that the chosen ray
+    /// carries the whole light
+    pub fn choose(&self) -> Vec3 {
+        match self.refracted {
+            Some(refracted) if self.reflectance <= normal_random() => refracted,
+            _ => self.reflected,
+        }
+    }
+
+    /// Both directions with the fraction of the light they carry, for the
+    /// integrators tracing the two rays
+    pub fn weighted(&self) -> impl Iterator<Item = (Vec3, f64)> {
+        let refracted = self.refracted.map(|d| (d, 1.0 - self.reflectance));
+        std::iter::once((self.reflected, self.reflectance)).chain(refracted)
+    }
+}
+
+/// Reflective surface, the reflected direction is blurred more and more as
+/// the fuzz grows from 0 (perfect mirror) to 1. The fuzz can differ along the
+/// tangent and the bitangent of the surface, stretching the highlights like
+/// on brushed metal.
+#[derive(Debug)]
+pub struct Metal {
+    pub albedo: Color,
+    /// Fuzz along the tangent of the surface, see [`HitRecord::tangent`]
+    pub fuzz_u: f64,
+    /// Fuzz along the bitangent, the normal crossed with the tangent
+    pub fuzz_v: f64,
+}
+
+impl Metal {
+    pub fn new(albedo: Color, fuzz: f64) -> Self {
+        Self::anisotropic(albedo, fuzz, fuzz)
+    }
+
+    pub fn anisotropic(albedo: Color, fuzz_u: f64, fuzz_v: f64) -> Self {
+        Self {
+            albedo,
+            fuzz_u: fuzz_u.clamp(0.0, 1.0),
+            fuzz_v: fuzz_v.clamp(0.0, 1.0),
+        }
+    }
+}
+
+impl Material for Metal {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        // random offset scaled along each axis of the surface frame, the
+        // normal axis with the mean fuzz
+        let (t, b) = surface_frame(&rec.normal, &rec.tangent);
+        let r = sampler::next_on_sphere();
+        let fuzz_n = 0.5 * (self.fuzz_u + self.fuzz_v);
+        let offset = self.fuzz_u * r.dot(&t) * t
+            + self.fuzz_v * r.dot(&b) * b
+            + fuzz_n * r.dot(&rec.normal) * rec.normal;
+
+        let reflected = reflect(&ray_in.direction().normal(), &rec.normal) + offset;
+        // fuzzed below the surface, absorbed
+        if reflected.dot(&rec.normal) <= 0.0 {
+            return None;
+        }
+        Some(ScatterRecord {
+            attenuation: self.albedo,
+            scattered: Ray::new(rec.point, reflected),
+        })
+    }
+
+    fn base_color(&self, _rec: &HitRecord) -> Color {
+        self.albedo
+    }
+}
+
+/// Glossy surface between [`Metal`] and [`Lambertian`]: the directions are
+/// importance sampled from a Phong lobe around the mirror direction, whose
+/// exponent is `2 / roughness^2 - 1`. A roughness of 0 is a perfect mirror, a
+/// roughness of 1 a cosine lobe, diffuse at normal incidence.
+#[derive(Debug)]
+pub struct Glossy {
+    pub albedo: Color,
+    /// In `[0, 1]`
+    pub roughness: f64,
+}
+
+impl Glossy {
+    pub fn new(albedo: Color, roughness: f64) -> Self {
+        Self {
+            albedo,
+            roughness: roughness.clamp(0.0, 1.0),
+        }
+    }
+
+    /// Exponent of the Phong lobe, infinite for a mirror
+    pub fn exponent(&self) -> f64 {
+        2.0 / (self.roughness * self.roughness) - 1.0
+    }
+
+    /// Probability density with respect to the solid angle that the ray
+    /// coming along `direction_in` is scattered along `direction` by the
+    /// surface with unit normal `normal`, for the multiple importance
+    /// sampling. A mirror only scatters along a single direction, a delta
+    /// distribution that no other strategy can sample, so its density is 0.
+    pub fn pdf(&self, direction_in: &Vec3, normal: &Vec3, direction: &Vec3) -> f64 {
+        if self.roughness == 0.0 {
+            return 0.0;
+        }
+        let mirror = reflect(&direction_in.normal(), normal);
+        let cosine = mirror.dot(&direction.normal());
+        if cosine <= 0.0 || normal.dot(direction) <= 0.0 {
+            return 0.0;
+        }
+        let n = self.exponent();
+        (n + 1.0) / (2.0 * PI) * cosine.powf(n)
+    }
+}
+
+impl Material for Glossy {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let mirror = reflect(&ray_in.direction().normal(), &rec.normal);
+        let direction = if self.roughness > 0.0 {
+            let (s, t) = sampler::next_2d();
+            let z = s.powf(1.0 / (self.exponent() + 1.0));
+            let r = (1.0 - z * z).max(0.0).sqrt();
+            let phi = 2.0 * PI * t;
+            let (t, b) = tangent_frame(&mirror, &any_tangent(&mirror));
+            r * phi.cos() * t + r * phi.sin() * b + z * mirror
+        } else {
+            mirror
+        };
+
+        // part of the lobe below the surface, absorbed
+        if direction.dot(&rec.normal) <= 0.0 {
+            return None;
+        }
+        Some(ScatterRecord {
+            attenuation: self.albedo,
+            scattered: Ray::new(rec.point, direction),
+        })
+    }
+
+    /// The mirror has no lobe the lights can sample
+    fn glossy_lobe(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Option<(Color, f64)> {
+        if self.roughness == 0.0 {
+            return None;
+        }
+        let pdf = self.pdf(ray_in.direction(), &rec.normal, direction);
+        Some((self.albedo, pdf))
+    }
+
+    fn base_color(&self, _rec: &HitRecord) -> Color {
+        self.albedo
+    }
+}
+
+/// Clear material refracting the light, like glass or water, that reflects
+/// part of it according to the Fresnel equations
+#[derive(Debug)]
+pub struct Dielectric {
+    /// Refraction index relative to the medium around the surface
+    pub refraction_index: f64,
+}
+
+impl Dielectric {
+    pub fn new(refraction_index: f64) -> Self {
+        Self { refraction_index }
+    }
+}
+
+impl Material for Dielectric {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let eta = if rec.front_face == Some(false) {
+            self.refraction_index
+        } else {
+            1.0 / self.refraction_index
+        };
+
+        let direction = FresnelSplit::new(ray_in.direction(), &rec.normal, eta).choose();
+        Some(ScatterRecord {
+            attenuation: Color::new(1., 1., 1.),
+            scattered: Ray::new(rec.point, direction),
+        })
+    }
+
+    fn base_color(&self, _rec: &HitRecord) -> Color {
+        Color::new(1., 1., 1.)
+    }
+}
+
+/// Translucent material like wax or skin: the light enters the surface
+/// diffusely and wanders inside the object, scattering in random directions
+/// and being partly absorbed at every event, until it gets out
+#[derive(Debug)]
+pub struct Subsurface {
+    /// Fraction of the light surviving each scattering event inside
+    pub albedo: Color,
+    /// Mean distance travelled inside between two scattering events, the
+    /// larger the more translucent
+    pub radius: f64,
+}
+
+impl Subsurface {
+    pub fn new(albedo: Color, radius: f64) -> Self {
+        Self { albedo, radius }
+    }
+}
+
+impl Material for Subsurface {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        // entering: diffuse transmission through the surface
+        if rec.front_face != Some(false) {
+            return Some(ScatterRecord {
+                attenuation: Color::new(1., 1., 1.),
+                scattered: Ray::new(rec.point, DiffuseMode::Cosine.sample(&-rec.normal)),
+            });
+        }
+
+        // inside: exponential free flight, if it ends before the surface the
+        // light scatters there, otherwise it gets out
+        let distance = rec.t * ray_in.direction().length();
+        let flight = -self.radius * (1.0 - normal_random()).ln();
+        if flight < distance {
+            let event = ray_in.at(rec.t * flight / distance);
+            return Some(ScatterRecord {
+                attenuation: self.albedo,
+                scattered: Ray::new(event, Vec3::unit_random_on_sphere()),
+            });
+        }
+        Some(ScatterRecord {
+            attenuation: Color::new(1., 1., 1.),
+            scattered: Ray::new(rec.point, *ray_in.direction()),
+        })
+    }
+
+    fn base_color(&self, _rec: &HitRecord) -> Color {
+        self.albedo
+    }
+}
+
+/// Ready-made materials with physically plausible parameters
+pub mod presets {
+    use super::*;
+
+    /// Polished gold, reflectance of gold at normal incidence
+    pub fn gold() -> Metal {
+        Metal::new(Color::new(1.0, 0.71, 0.29), 0.05)
+    }
+
+    /// Window glass
+    pub fn glass() -> Dielectric {
+        Dielectric::new(1.5)
+    }
+
+    /// Water at room temperature
+    pub fn water() -> Dielectric {
+        Dielectric::new(1.333)
+    }
+
+    /// Silvered mirror, a perfectly sharp reflector
+    pub fn mirror() -> Metal {
+        Metal::new(Color::new(0.95, 0.95, 0.95), 0.0)
+    }
+
+    /// Plain diffuse surface of the given color
+    pub fn matte(color: Color) -> Lambertian {
+        Lambertian::new(color)
+    }
+}
+
+/// Surface emitting light uniformly, it doesn't reflect anything
+#[derive(Debug)]
+pub struct DiffuseLight {
+    pub color: Color,
+    /// Multiplier of `color`, so that the brightness is set independently
+    /// from the tint
+    pub intensity: f64,
+    /// A one-sided light only emits from the front face of the surface, the
+    /// side the outward normal points to
+    pub two_sided: bool,
+}
+
+impl DiffuseLight {
+    pub fn new(color: Color, intensity: f64) -> Self {
+        Self {
+            color,
+            intensity,
+            two_sided: true,
+        }
+    }
+
+    pub fn one_sided(color: Color, intensity: f64) -> Self {
+        Self {
+            two_sided: false,
+            ..Self::new(color, intensity)
+        }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        if self.two_sided || rec.front_face == Some(true) {
+            self.intensity * self.color
+        } else {
+            Color::zero()
+        }
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.intensity > 0.0 && self.color != Color::zero()
+    }
+
+    fn base_color(&self, _rec: &HitRecord) -> Color {
+        self.color
+    }
+}
+
+/// Adds surface detail to a material by perturbing its normal with a
+/// tangent-space normal map: the red, green and blue channels of the texture,
+/// remapped from `[0, 1]` to `[-1, 1]`, are the components of the normal along
+/// the tangent, the bitangent and the geometric normal
+#[derive(Debug)]
+pub struct NormalMapped {
+    pub material: Arc<dyn Material>,
+    pub normal_map: Arc<dyn Texture>,
+}
+
+impl NormalMapped {
+    pub fn new<M: Material + 'static, T: Texture + 'static>(material: M, normal_map: T) -> Self {
+        Self {
+            material: Arc::new(material),
+            normal_map: Arc::new(normal_map),
+        }
+    }
+}
+
+impl Material for NormalMapped {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        self.material.scatter(ray_in, rec)
+    }
+
+    fn scatter_with_mode(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        mode: DiffuseMode,
+    ) -> Option<ScatterRecord> {
+        self.material.scatter_with_mode(ray_in, rec, mode)
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        self.material.emitted(rec)
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.material.is_emissive()
+    }
+
+    fn diffuse_albedo(&self, rec: &HitRecord) -> Color {
+        self.material.diffuse_albedo(rec)
+    }
+
+    fn glossy_lobe(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Option<(Color, f64)> {
+        self.material.glossy_lobe(ray_in, rec, direction)
+    }
+
+    fn base_color(&self, rec: &HitRecord) -> Color {
+        self.material.base_color(rec)
+    }
+
+    fn shading_normal(&self, rec: &HitRecord) -> Vec3 {
+        let n = self.material.shading_normal(rec);
+
+        let (t, b) = surface_frame(&n, &rec.tangent);
+
+        let c = self.normal_map.value(rec.u, rec.v, &rec.point);
+        let local = 2.0 * c - Vec3::new(1., 1., 1.);
+        (local.x() * t + local.y() * b + local.z() * n).normal()
+    }
+}
+
+#[cfg(test)]
+mod material_tests {
+    use super::*;
+    use crate::hittable::Hittable;
+    use crate::interval::Interval;
+    use crate::math::Point3;
+    use crate::random;
+    use crate::sphere::Sphere;
+
+    #[test]
+    fn one_sided_light() {
+        let white = Color::new(1., 1., 1.);
+        let panel =
+            Sphere::new(Point3::zero(), 1.).with_material(DiffuseLight::one_sided(white, 1.));
+        let lamp = Sphere::new(Point3::zero(), 1.).with_material(DiffuseLight::new(white, 1.));
+
+        let outside = Ray::new(Point3::new(0., 0., 5.), Vec3::new(0., 0., -1.));
+        let inside = Ray::new(Point3::zero(), Vec3::new(0., 0., -1.));
+
+        let front = panel.hit(&outside, Interval::positive()).unwrap();
+        let back = panel.hit(&inside, Interval::positive()).unwrap();
+        assert_eq!(front.material.emitted(&front), white);
+        assert_eq!(back.material.emitted(&back), Color::zero());
+
+        let back = lamp.hit(&inside, Interval::positive()).unwrap();
+        assert_eq!(back.material.emitted(&back), white);
+    }
+
+    #[test]
+    fn diffuse_modes() {
+        let normal = Vec3::new(0., 0.6, 0.8);
+        for mode in [
+            DiffuseMode::SphereOffset,
+            DiffuseMode::Hemisphere,
+            DiffuseMode::Cosine,
+        ] {
+            for _ in 0..1000 {
+                assert!(mode.sample(&normal).dot(&normal) > 0.0, "{:?}", mode);
+            }
+        }
+
+        for _ in 0..100 {
+            let d = DiffuseMode::Cosine.sample(&normal);
+            assert!((d.length() - 1.0).abs() < 1e-12);
+        }
+
+        let lambertian = Lambertian::new(Color::new(0.5, 0.5, 0.5));
+        let sphere = Sphere::new(Point3::zero(), 1.).with_material(lambertian);
+        let ray = Ray::new(Point3::new(0., 0., 5.), Vec3::new(0., 0., -1.));
+        let rec = sphere.hit(&ray, Interval::positive()).unwrap();
+        for _ in 0..100 {
+            let scatter = rec
+                .material
+                .scatter_with_mode(&ray, &rec, DiffuseMode::Hemisphere)
+                .unwrap();
+            assert!(scatter.scattered.direction().dot(&rec.normal) > 0.0);
+        }
+    }
+
+    #[test]
+    fn normal_mapping() {
+        let gray = Color::new(0.5, 0.5, 0.5);
+        let ray = Ray::new(Point3::new(0.3, 0.2, 5.), Vec3::new(0., 0., -1.));
+        let shading_normal = |map: Color| {
+            let sphere = Sphere::new(Point3::zero(), 1.).with_material(NormalMapped::new(
+                Lambertian::new(gray),
+                SolidColor::new(map),
+            ));
+            let rec = sphere.hit(&ray, Interval::positive()).unwrap();
+            (rec.material.shading_normal(&rec), rec)
+        };
+
+        let (flat, rec) = shading_normal(Color::new(0.5, 0.5, 1.0));
+        assert!(flat.approx_eq(&rec.normal, 1e-12));
+
+        // tilted toward the tangent, still a unit vector facing out
+        let (bumped, rec) = shading_normal(Color::new(1.0, 0.5, 1.0));
+        assert!((bumped.length() - 1.0).abs() < 1e-12);
+        assert!(bumped.dot(&rec.tangent) > 0.5);
+        assert!(bumped.dot(&rec.normal) > 0.5);
+    }
+
+    #[test]
+    fn light_intensity() {
+        let daylight = Color::new(1.0, 0.9, 0.8);
+        let ray = Ray::new(Point3::new(0., 0., 5.), Vec3::new(0., 0., -1.));
+        let emitted = |intensity: f64| {
+            let lamp = Sphere::new(Point3::zero(), 1.)
+                .with_material(DiffuseLight::new(daylight, intensity));
+            let rec = lamp.hit(&ray, Interval::positive()).unwrap();
+            rec.material.emitted(&rec)
+        };
+
+        assert_eq!(emitted(5000.), 5000. * daylight);
+        assert_eq!(emitted(2. * 5000.).length(), 2. * emitted(5000.).length());
+
+        assert!(DiffuseLight::one_sided(daylight, 1.).is_emissive());
+        assert!(!DiffuseLight::new(daylight, 0.).is_emissive());
+        assert!(!Lambertian::new(daylight).is_emissive());
+        let mapped = NormalMapped::new(
+            DiffuseLight::new(daylight, 1.),
+            SolidColor::new(Color::new(0.5, 0.5, 1.)),
+        );
+        assert!(mapped.is_emissive());
+    }
+
+    #[test]
+    fn presets() {
+        assert!((presets::glass().refraction_index - 1.5).abs() < 0.05);
+        assert_eq!(presets::mirror().fuzz_u, 0.0);
+
+        let ray = Ray::new(Point3::new(0., 0., 5.), Vec3::new(0., 0., -1.));
+        let mirror = Sphere::new(Point3::zero(), 1.).with_material(presets::mirror());
+        let rec = mirror.hit(&ray, Interval::positive()).unwrap();
+        let reflected = rec.material.scatter(&ray, &rec).unwrap().scattered;
+        assert!(
+            reflected
+                .direction()
+                .approx_eq(&Vec3::new(0., 0., 1.), 1e-12)
+        );
+
+        // a ray through the center of a glass sphere is not deviated, apart
+        // from the few reflected back
+        let glass = Sphere::new(Point3::zero(), 1.).with_material(presets::glass());
+        let rec = glass.hit(&ray, Interval::positive()).unwrap();
+        let refracted = (0..100)
+            .filter_map(|_| rec.material.scatter(&ray, &rec))
+            .filter(|s| s.scattered.direction().approx_eq(ray.direction(), 1e-12))
+            .count();
+        assert!(refracted > 80);
+
+        let gold = presets::gold().albedo;
+        assert!(gold.x() > gold.y() && gold.y() > gold.z());
+    }
+
+    #[test]
+    fn subsurface_transmission() {
+        // fraction of the light entering the front of a sphere of wax that
+        // gets out of its back half
+        let transmitted = |radius: f64| {
+            let wax = Subsurface::new(Color::new(0.9, 0.9, 0.9), 0.5);
+            let sphere = Sphere::new(Point3::zero(), radius).with_material(wax);
+            let mut total = 0.0;
+            for _ in 0..2000 {
+                let mut ray = Ray::new(Point3::new(0., 0., 2. * radius), Vec3::new(0., 0., -1.));
+                let mut throughput = 1.0;
+                while let Some(rec) = sphere.hit(&ray, Interval::new(1e-9, f64::MAX)) {
+                    let scatter = rec.material.scatter(&ray, &rec).unwrap();
+                    throughput *= scatter.attenuation.x();
+                    ray = scatter.scattered;
+                }
+                if ray.origin().z() < 0.0 {
+                    total += throughput;
+                }
+            }
+            total / 2000.
+        };
+
+        let thin = transmitted(0.1);
+        let thick = transmitted(5.0);
+        assert!(thin > 0.3, "{}", thin);
+        assert!(thin > 2.0 * thick, "{} {}", thin, thick);
+    }
+
+    #[test]
+    fn anisotropic_metal() {
+        let albedo = Color::new(0.8, 0.8, 0.8);
+        let ray = Ray::new(Point3::new(0.3, 0.2, 5.), Vec3::new(0., 0., -1.));
+        let reflections = |metal: Metal| {
+            let sphere = Sphere::new(Point3::zero(), 1.).with_material(metal);
+            let rec = sphere.hit(&ray, Interval::positive()).unwrap();
+            random::seed(3);
+            (0..100)
+                .map(|_| rec.material.scatter(&ray, &rec).map(|s| s.scattered))
+                .collect::<Vec<_>>()
+        };
+
+        // equal fuzz along both axes: the isotropic offset of a random unit
+        // vector scaled by the fuzz
+        let sphere = Sphere::new(Point3::zero(), 1.);
+        let rec = sphere.hit(&ray, Interval::positive()).unwrap();
+        let mirror = reflect(&ray.direction().normal(), &rec.normal);
+        random::seed(3);
+        let isotropic: Vec<Option<Vec3>> = (0..100)
+            .map(|_| {
+                let reflected = mirror + 0.3 * Vec3::unit_random_on_sphere();
+                (reflected.dot(&rec.normal) > 0.0).then_some(reflected)
+            })
+            .collect();
+        let anisotropic = reflections(Metal::anisotropic(albedo, 0.3, 0.3));
+        for (a, b) in isotropic.iter().zip(&anisotropic) {
+            match (a, b) {
+                (Some(a), Some(b)) => assert!(a.approx_eq(b.direction(), 1e-12)),
+                _ => assert!(a.is_none() && b.is_none()),
+            }
+        }
+
+        // brushed along the tangent: the spread is larger along it
+        let (t, b) = surface_frame(&rec.normal, &rec.tangent);
+        let spread = |axis: &Vec3| {
+            reflections(Metal::anisotropic(albedo, 0.5, 0.05))
+                .iter()
+                .flatten()
+                .map(|r| (*r.direction() - mirror).dot(axis).abs())
+                .sum::<f64>()
+        };
+        assert!(spread(&t) > 3.0 * spread(&b));
+    }
+
+    #[test]
+    fn fresnel_split_grazing() {
+        let normal = Vec3::new(0., 1., 0.);
+        let split_at = |angle: f64| {
+            let direction = Vec3::new(angle.sin(), -angle.cos(), 0.);
+            FresnelSplit::new(&direction, &normal, 1. / 1.5)
+        };
+
+        let angles = [0., 0.3, 0.6, 0.9, 1.2, 1.5];
+        let reflectances: Vec<f64> = angles.iter().map(|&a| split_at(a).reflectance).collect();
+        assert!(reflectances.windows(2).all(|w| w[0] < w[1]));
+        assert!((reflectances[0] - 0.04).abs() < 1e-12);
+        assert!(reflectances[5] > 0.6);
+
+        // the weights of the two rays share the whole light
+        let split = split_at(0.6);
+        assert_eq!(split.weighted().map(|(_, w)| w).sum::<f64>(), 1.0);
+        assert_eq!(split.weighted().count(), 2);
+
+        // leaving the glass past the critical angle, all reflected
+        let direction = Vec3::new(0.9f64.sin(), -0.9f64.cos(), 0.);
+        let total = FresnelSplit::new(&direction, &normal, 1.5);
+        assert_eq!(total.refracted, None);
+        assert_eq!(total.choose(), total.reflected);
+        assert_eq!(
+            total.weighted().collect::<Vec<_>>(),
+            [(total.reflected, 1.0)]
+        );
+    }
+
+    #[test]
+    fn glossy_roughness() {
+        let albedo = Color::new(0.8, 0.8, 0.8);
+        let ray = Ray::new(Point3::new(0.3, 0.2, 5.), Vec3::new(0., 0., -1.));
+        let sphere = Sphere::new(Point3::zero(), 1.);
+        let rec = sphere.hit(&ray, Interval::positive()).unwrap();
+        let mirror = reflect(ray.direction(), &rec.normal);
+        let scattered = |glossy: &Glossy| {
+            (0..2000)
+                .filter_map(|_| glossy.scatter(&ray, &rec))
+                .map(|s| s.scattered.direction().normal())
+                .collect::<Vec<_>>()
+        };
+
+        // smooth: the mirror direction
+        for direction in scattered(&Glossy::new(albedo, 0.)) {
+            assert!(direction.approx_eq(&mirror, 1e-12));
+        }
+        let mirror_pdf =
+            |direction: &Vec3| Glossy::new(albedo, 0.).pdf(ray.direction(), &rec.normal, direction);
+        assert_eq!(mirror_pdf(&mirror), 0.0);
+        assert_eq!(mirror_pdf(&(mirror + 0.1 * rec.normal)), 0.0);
+        let almost_smooth = scattered(&Glossy::new(albedo, 0.01));
+        assert!(almost_smooth.iter().all(|d| d.dot(&mirror) > 0.999));
+
+        // rough at normal incidence: the cosine distribution of a diffuse
+        // surface, whose mean cosine is 2/3
+        let ray = Ray::new(Point3::new(0., 0., 5.), Vec3::new(0., 0., -1.));
+        let rec = sphere.hit(&ray, Interval::positive()).unwrap();
+        let rough = Glossy::new(albedo, 1.);
+        let cosines: Vec<f64> = (0..4000)
+            .filter_map(|_| rough.scatter(&ray, &rec))
+            .map(|s| s.scattered.direction().normal().dot(&rec.normal))
+            .collect();
+        assert_eq!(cosines.len(), 4000);
+        let mean = cosines.iter().sum::<f64>() / cosines.len() as f64;
+        assert!((mean - 2. / 3.).abs() < 0.02, "{}", mean);
+        let direction = Vec3::new(0.3, 0.4, 1.);
+        let pdf = rough.pdf(ray.direction(), &rec.normal, &direction);
+        let diffuse_pdf = DiffuseMode::Cosine.pdf(&rec.normal, &direction);
+        assert!((pdf - diffuse_pdf).abs() < 1e-12);
+    }
+}
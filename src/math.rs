@@ -1,5 +1,5 @@
 use crate::random::normal_random;
-use std::ops::{Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Neg, Sub};
+use std::ops::{Add, AddAssign, Deref, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Neg, Sub};
 
 /// Linear interpolation between two vectors, this functions implements
 /// the following equation:
@@ -10,7 +10,33 @@ pub fn lerp(start: &Vec3, end: &Vec3, t: f64) -> Vec3 {
     (*start) * (1.0 - t) + (*end) * t
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+/// Schlick's approximation of the Fresnel reflectance of a dielectric:
+/// $$
+///    r0 + (1 - r0) * (1 - cosine)^5, r0 = ((1 - n) / (1 + n))^2
+/// $$
+/// where `cosine` is the cosine of the incidence angle and `n` the ratio of
+/// the refraction indices.
+pub fn reflectance(cosine: f64, refraction_index: f64) -> f64 {
+    let r0 = (1.0 - refraction_index) / (1.0 + refraction_index);
+    let r0 = r0 * r0;
+    r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
+}
+
+/// Power heuristic weight of a sample taken by a strategy with density `f`
+/// when another strategy samples the same direction with density `g`:
+/// $$
+///    f^2 / (f^2 + g^2)
+/// $$
+/// An infinite `f` (a point light) always gets the whole weight.
+pub fn power_heuristic(f: f64, g: f64) -> f64 {
+    if f.is_infinite() {
+        return 1.0;
+    }
+    let (f2, g2) = (f * f, g * g);
+    if f2 + g2 > 0.0 { f2 / (f2 + g2) } else { 0.0 }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
 pub struct Vec3 {
     data: [f64; 3],
 }
@@ -35,7 +61,7 @@ impl Vec3 {
         loop {
             let vec = Vec3::random(-1.0, 1.0);
             let len_sq = vec.length2();
-            if 1e-100 <= len_sq && len_sq <= 1.0 {
+            if (1e-100..=1.0).contains(&len_sq) {
                 return vec / len_sq.sqrt();
             }
         }
@@ -48,6 +74,22 @@ impl Vec3 {
         if normal.dot(&vec) > 0.0 { vec } else { -vec }
     }
 
+    /// Point at distance `radius` from the origin with the y axis as the pole:
+    /// `theta` is the angle from +y and `phi` the angle around y, from +x
+    /// toward +z. Both are in radians.
+    /// $$
+    ///    (r sin(theta) cos(phi), r cos(theta), r sin(theta) sin(phi))
+    /// $$
+    pub fn from_spherical(radius: f64, theta: f64, phi: f64) -> Self {
+        let (sin_theta, cos_theta) = theta.sin_cos();
+        let (sin_phi, cos_phi) = phi.sin_cos();
+        Self::new(
+            radius * sin_theta * cos_phi,
+            radius * cos_theta,
+            radius * sin_theta * sin_phi,
+        )
+    }
+
     /// Generate a random vector in which each component is in the range [min, max]
     pub fn random(min: f64, max: f64) -> Self {
         assert!(min <= max);
@@ -72,17 +114,75 @@ impl Vec3 {
         self.data[2]
     }
 
+    /// Components narrowed to `f32`, for the buffers handed to a GPU or to
+    /// other libraries
+    pub fn to_f32_array(&self) -> [f32; 3] {
+        self.data.map(|c| c as f32)
+    }
+
+    /// Checks if every component differs from the one of `other` by at most
+    /// `eps`
+    pub fn approx_eq(&self, other: &Vec3, eps: f64) -> bool {
+        self.components()
+            .zip(other.components())
+            .all(|(a, b)| (a - b).abs() <= eps)
+    }
+
+    pub fn max_component(&self) -> f64 {
+        self.x().max(self.y()).max(self.z())
+    }
+
+    pub fn min_component(&self) -> f64 {
+        self.x().min(self.y()).min(self.z())
+    }
+
+    /// Index of the largest component, the last one on ties
+    pub fn max_axis(&self) -> usize {
+        let (x, y, z) = (self.x(), self.y(), self.z());
+        if x > y && x > z {
+            0
+        } else if y > z {
+            1
+        } else {
+            2
+        }
+    }
+
+    /// Iterates over the x, y and z components
+    pub fn components(&self) -> impl Iterator<Item = f64> + '_ {
+        self.into_iter()
+    }
+
+    /// Vector of `f` applied to every component, e.g.
+    /// `color.map(linear_to_gamma)`
+    pub fn map(&self, f: impl Fn(f64) -> f64) -> Vec3 {
+        Vec3 {
+            data: self.data.map(f),
+        }
+    }
+
     pub fn length2(&self) -> f64 {
         self.x() * self.x() + self.y() * self.y() + self.z() * self.z()
     }
 
     pub fn length(&self) -> f64 {
-        return self.length2().sqrt();
+        self.length2().sqrt()
+    }
+
+    /// Distance between the points `self` and `other`
+    pub fn distance(&self, other: &Vec3) -> f64 {
+        self.distance2(other).sqrt()
+    }
+
+    /// Squared distance between the points `self` and `other`, cheaper than
+    /// [`Vec3::distance`] when only comparing distances
+    pub fn distance2(&self, other: &Vec3) -> f64 {
+        (*self - *other).length2()
     }
 
     /// Return a new normalized Vec3 with the same direction as the original Vec3
     pub fn normal(&self) -> Vec3 {
-        return self.clone() / self.length();
+        *self / self.length()
     }
 
     pub fn dot(&self, v: &Vec3) -> f64 {
@@ -92,10 +192,31 @@ impl Vec3 {
     pub fn cross(&self, v: &Vec3) -> Vec3 {
         Vec3::new(
             self[1] * v[2] - self[2] * v[1],
-            self[0] * v[2] - self[2] * v[0],
+            self[2] * v[0] - self[0] * v[2],
             self[0] * v[1] - self[1] * v[0],
         )
     }
+
+    /// Rotates the vector by `angle` radians around the unit vector `axis`,
+    /// counterclockwise looking from the tip of the axis (Rodrigues' formula):
+    /// $$
+    ///    v cos(a) + (k x v) sin(a) + k (k . v) (1 - cos(a))
+    /// $$
+    pub fn rotate_around(&self, axis: &Vec3, angle: f64) -> Vec3 {
+        let (sin, cos) = angle.sin_cos();
+        *self * cos + axis.cross(self) * sin + *axis * (axis.dot(self) * (1.0 - cos))
+    }
+
+    /// Component of the vector along `axis`, which doesn't need to be normalized
+    pub fn project_onto(&self, axis: &Vec3) -> Vec3 {
+        (self.dot(axis) / axis.length2()) * *axis
+    }
+
+    /// Component of the vector perpendicular to `axis`, so that adding it to
+    /// the projection gives back the vector
+    pub fn reject_from(&self, axis: &Vec3) -> Vec3 {
+        *self - self.project_onto(axis)
+    }
 }
 
 impl Add<Vec3> for Vec3 {
@@ -222,12 +343,116 @@ impl IndexMut<usize> for Vec3 {
     }
 }
 
+impl From<[f64; 3]> for Vec3 {
+    fn from(data: [f64; 3]) -> Self {
+        Self { data }
+    }
+}
+
+impl From<(f64, f64, f64)> for Vec3 {
+    fn from((x, y, z): (f64, f64, f64)) -> Self {
+        Self::new(x, y, z)
+    }
+}
+
+impl IntoIterator for Vec3 {
+    type Item = f64;
+    type IntoIter = std::array::IntoIter<f64, 3>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.data.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Vec3 {
+    type Item = f64;
+    type IntoIter = std::iter::Copied<std::slice::Iter<'a, f64>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.data.iter().copied()
+    }
+}
+
+/// Vector of unit length, for the normals and the directions that the code
+/// using them doesn't need to normalize again
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UnitVec3(Vec3);
+
+impl UnitVec3 {
+    /// Normalizes `v`, which must not be zero (checked in debug builds)
+    pub fn new(v: Vec3) -> Self {
+        Self::new_normalized(v.normal())
+    }
+
+    /// Wraps `v` assuming it is already normalized, only checked in debug
+    /// builds
+    pub fn new_normalized(v: Vec3) -> Self {
+        debug_assert!(
+            (v.length2() - 1.0).abs() < 1e-9,
+            "{:?} is not a unit vector",
+            v
+        );
+        Self(v)
+    }
+
+    pub fn get(&self) -> Vec3 {
+        self.0
+    }
+}
+
+impl Deref for UnitVec3 {
+    type Target = Vec3;
+
+    fn deref(&self) -> &Vec3 {
+        &self.0
+    }
+}
+
+impl From<UnitVec3> for Vec3 {
+    fn from(v: UnitVec3) -> Self {
+        v.0
+    }
+}
+
+impl Neg for UnitVec3 {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self(-self.0)
+    }
+}
+
 // Annotation used to tell rust compiler to compile this code only if running tests
 #[cfg(test)]
 mod vec3_tests {
     // import in the module all the stuff in the upper context
     use super::*;
 
+    #[test]
+    fn unit_vector() {
+        let u = UnitVec3::new(Vec3::new(3., 0., 0.));
+        assert_eq!(u.get(), Vec3::new(1., 0., 0.));
+        assert_eq!(u.dot(&Vec3::new(2., 5., 0.)), 2.);
+        assert_eq!(Vec3::from(-u), Vec3::new(-1., 0., 0.));
+
+        let diagonal = UnitVec3::new(Vec3::new(1., 1., 1.));
+        assert!((diagonal.length() - 1.).abs() < 1e-12);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "is not a unit vector")]
+    fn zero_unit_vector() {
+        UnitVec3::new(Vec3::zero());
+    }
+
+    #[test]
+    fn f32_array() {
+        let v = Vec3::new(0.1, -2.5, 1e40);
+        assert_eq!(v.to_f32_array(), [0.1f64 as f32, -2.5, f32::INFINITY]);
+        assert_eq!(Vec3::new(1., 2., 3.).to_f32_array(), [1., 2., 3.]);
+    }
+
     #[test]
     fn accessors() {
         let p = Vec3::new(1.0, 2.0, 3.0);
@@ -256,11 +481,98 @@ mod vec3_tests {
         }
 
         for _ in 0..100 {
-            let p = Vec3::random_on_unit_sphere();
+            let p = Vec3::unit_random_on_sphere();
             assert!(p.length() <= 1.0 + 1e-12 /* remove numerical error*/);
         }
     }
 
+    #[test]
+    fn cross() {
+        let x = Vec3::new(1., 0., 0.);
+        let y = Vec3::new(0., 1., 0.);
+        let z = Vec3::new(0., 0., 1.);
+        assert_eq!(x.cross(&y), z);
+        assert_eq!(y.cross(&z), x);
+        assert_eq!(z.cross(&x), y);
+    }
+
+    #[test]
+    fn spherical() {
+        use std::f64::consts::{FRAC_PI_2, PI};
+
+        let equator = Vec3::from_spherical(1.0, FRAC_PI_2, 0.0);
+        assert!(equator.approx_eq(&Vec3::new(1., 0., 0.), 1e-12));
+        assert!((equator.length() - 1.0).abs() < 1e-12);
+
+        let quarter = Vec3::from_spherical(2.0, FRAC_PI_2, FRAC_PI_2);
+        assert!(quarter.approx_eq(&Vec3::new(0., 0., 2.), 1e-12));
+
+        let pole = Vec3::from_spherical(3.0, 0.0, PI);
+        assert!(pole.approx_eq(&Vec3::new(0., 3., 0.), 1e-12));
+    }
+
+    #[test]
+    fn rotation() {
+        let x = Vec3::new(1., 0., 0.);
+        let z = Vec3::new(0., 0., 1.);
+        let rotated = x.rotate_around(&z, std::f64::consts::FRAC_PI_2);
+        assert!(rotated.approx_eq(&Vec3::new(0., 1., 0.), 1e-12));
+
+        // the component along the axis is kept
+        let p = Vec3::new(1., 2., 3.);
+        assert!(p.rotate_around(&z, 1.0).z() == 3.0);
+        assert!((p.rotate_around(&z, 1.0).length() - p.length()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn distances() {
+        let a = Point3::zero();
+        let b = Point3::new(3., 4., 0.);
+        assert_eq!(a.distance(&b), 5.0);
+        assert_eq!(a.distance2(&b), 25.0);
+        assert_eq!(b.distance(&a), 5.0);
+    }
+
+    #[test]
+    fn projection() {
+        let p = Vec3::new(1., 1., 0.);
+        let axis = Vec3::new(1., 0., 0.);
+        assert_eq!(p.project_onto(&axis), Vec3::new(1., 0., 0.));
+        assert_eq!(p.reject_from(&axis), Vec3::new(0., 1., 0.));
+        assert_eq!(p.project_onto(&(3.0 * axis)), Vec3::new(1., 0., 0.));
+    }
+
+    #[test]
+    fn schlick_reflectance() {
+        let n = 1.5;
+        let expected = ((n - 1.0) / (n + 1.0)) * ((n - 1.0) / (n + 1.0));
+        assert!((reflectance(1.0, n) - expected).abs() < 1e-12);
+        assert!((reflectance(0.0, n) - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn iterate() {
+        let p = Vec3::new(1., 2., 3.);
+        assert_eq!(p.into_iter().collect::<Vec<f64>>(), vec![1.0, 2.0, 3.0]);
+        assert_eq!((&p).into_iter().collect::<Vec<f64>>(), vec![1.0, 2.0, 3.0]);
+        assert_eq!(p.components().sum::<f64>(), 6.0);
+    }
+
+    #[test]
+    fn conversions() {
+        let p = Vec3::new(1., 2., 3.);
+        assert_eq!(Vec3::from([1., 2., 3.]), p);
+        assert_eq!(Vec3::from((1., 2., 3.)), p);
+    }
+
+    #[test]
+    fn approx_eq() {
+        let p = Vec3::new(1., 2., 3.);
+        let q = p + Vec3::new(0., 1e-13, 0.);
+        assert!(p.approx_eq(&q, 1e-9));
+        assert!(!p.approx_eq(&q, 1e-15));
+    }
+
     #[test]
     fn access_index() {
         let p = Vec3::new(1.0, 2.0, 3.0);
@@ -276,4 +588,21 @@ mod vec3_tests {
         p[2] = 4.;
         assert_eq!(p[2], 4.0);
     }
+
+    #[test]
+    fn map() {
+        let v = Vec3::new(4., 9., 16.);
+        assert_eq!(v.map(f64::sqrt), Vec3::new(2., 3., 4.));
+        assert_eq!((-v).map(f64::abs), v);
+    }
+
+    #[test]
+    fn extreme_components() {
+        let v = Vec3::new(1., 3., 2.);
+        assert_eq!(v.max_axis(), 1);
+        assert_eq!(v.max_component(), 3.);
+        assert_eq!(v.min_component(), 1.);
+        assert_eq!(Vec3::new(-1., -3., -2.).max_axis(), 0);
+        assert_eq!(Vec3::new(2., 2., 2.).max_axis(), 2);
+    }
 }
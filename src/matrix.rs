@@ -0,0 +1,211 @@
+//! 4x4 matrices for affine transformations of points and vectors.
+//!
+//! Matrices are stored in row-major order and multiply column vectors, so
+//! `a * b` applies `b` first and then `a`.
+
+use crate::math::{Point3, Vec3};
+use std::ops::Mul;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mat4 {
+    /// `m[row][column]`
+    pub m: [[f64; 4]; 4],
+}
+
+impl Mat4 {
+    pub fn new(m: [[f64; 4]; 4]) -> Self {
+        Self { m }
+    }
+
+    pub fn identity() -> Self {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            row[i] = 1.0;
+        }
+        Self { m }
+    }
+
+    pub fn translation(offset: Vec3) -> Self {
+        let mut t = Mat4::identity();
+        t.m[0][3] = offset.x();
+        t.m[1][3] = offset.y();
+        t.m[2][3] = offset.z();
+        t
+    }
+
+    pub fn scale(factors: Vec3) -> Self {
+        let mut s = Mat4::identity();
+        s.m[0][0] = factors.x();
+        s.m[1][1] = factors.y();
+        s.m[2][2] = factors.z();
+        s
+    }
+
+    /// Counterclockwise rotation of `angle` radians about the x axis, looking
+    /// from the positive side of the axis
+    pub fn rotation_x(angle: f64) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        Mat4::new([
+            [1., 0., 0., 0.],
+            [0., cos, -sin, 0.],
+            [0., sin, cos, 0.],
+            [0., 0., 0., 1.],
+        ])
+    }
+
+    /// Counterclockwise rotation of `angle` radians about the y axis
+    pub fn rotation_y(angle: f64) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        Mat4::new([
+            [cos, 0., sin, 0.],
+            [0., 1., 0., 0.],
+            [-sin, 0., cos, 0.],
+            [0., 0., 0., 1.],
+        ])
+    }
+
+    /// Counterclockwise rotation of `angle` radians about the z axis
+    pub fn rotation_z(angle: f64) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        Mat4::new([
+            [cos, -sin, 0., 0.],
+            [sin, cos, 0., 0.],
+            [0., 0., 1., 0.],
+            [0., 0., 0., 1.],
+        ])
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, v) in row.iter_mut().enumerate() {
+                *v = self.m[j][i];
+            }
+        }
+        Self { m }
+    }
+
+    /// Inverse computed by Gauss-Jordan elimination, None if the matrix is
+    /// singular
+    pub fn inverse(&self) -> Option<Self> {
+        let mut a = self.m;
+        let mut inv = Mat4::identity().m;
+
+        for col in 0..4 {
+            // partial pivoting: use the row with the largest value
+            let pivot = (col..4).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+            if a[pivot][col].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+
+            let p = a[col][col];
+            for j in 0..4 {
+                a[col][j] /= p;
+                inv[col][j] /= p;
+            }
+
+            for row in 0..4 {
+                if row == col {
+                    continue;
+                }
+                let f = a[row][col];
+                for j in 0..4 {
+                    a[row][j] -= f * a[col][j];
+                    inv[row][j] -= f * inv[col][j];
+                }
+            }
+        }
+
+        Some(Self { m: inv })
+    }
+
+    /// Transforms a point, translations are applied
+    pub fn transform_point(&self, p: &Point3) -> Point3 {
+        let m = &self.m;
+        let x = m[0][0] * p.x() + m[0][1] * p.y() + m[0][2] * p.z() + m[0][3];
+        let y = m[1][0] * p.x() + m[1][1] * p.y() + m[1][2] * p.z() + m[1][3];
+        let z = m[2][0] * p.x() + m[2][1] * p.y() + m[2][2] * p.z() + m[2][3];
+        let w = m[3][0] * p.x() + m[3][1] * p.y() + m[3][2] * p.z() + m[3][3];
+        Point3::new(x, y, z) / w
+    }
+
+    /// Transforms a direction, translations are ignored
+    pub fn transform_vector(&self, v: &Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(
+            m[0][0] * v.x() + m[0][1] * v.y() + m[0][2] * v.z(),
+            m[1][0] * v.x() + m[1][1] * v.y() + m[1][2] * v.z(),
+            m[2][0] * v.x() + m[2][1] * v.y() + m[2][2] * v.z(),
+        )
+    }
+}
+
+impl Mul<Mat4> for Mat4 {
+    type Output = Mat4;
+
+    fn mul(self, b: Mat4) -> Self::Output {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, v) in row.iter_mut().enumerate() {
+                *v = (0..4).map(|k| self.m[i][k] * b.m[k][j]).sum();
+            }
+        }
+        Mat4 { m }
+    }
+}
+
+#[cfg(test)]
+mod matrix_tests {
+    use super::*;
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!(a.approx_eq(&b, 1e-12), "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn constructors() {
+        let v = Vec3::new(1., -2., 3.);
+        assert_eq!(Mat4::translation(v).transform_point(&Point3::zero()), v);
+        assert_eq!(Mat4::translation(v).transform_vector(&v), v);
+        assert_eq!(
+            Mat4::scale(v).transform_point(&Point3::new(2., 2., 2.)),
+            Vec3::new(2., -4., 6.)
+        );
+
+        let quarter = 90f64.to_radians();
+        let x = Vec3::new(1., 0., 0.);
+        let y = Vec3::new(0., 1., 0.);
+        let z = Vec3::new(0., 0., 1.);
+        assert_close(Mat4::rotation_z(quarter).transform_point(&x), y);
+        assert_close(Mat4::rotation_x(quarter).transform_point(&y), z);
+        assert_close(Mat4::rotation_y(quarter).transform_point(&z), x);
+
+        // translation applied after the rotation
+        let m = Mat4::translation(v) * Mat4::rotation_z(quarter);
+        assert_close(m.transform_point(&x), y + v);
+    }
+
+    #[test]
+    fn inverse() {
+        let a = Mat4::new([
+            [2., 0., 0., 1.],
+            [0., 0., -3., 2.],
+            [0., 1., 0., 3.],
+            [0., 0., 0., 1.],
+        ]);
+        let inv = a.inverse().unwrap();
+
+        let id = a * inv;
+        for i in 0..4 {
+            for j in 0..4 {
+                assert!((id.m[i][j] - Mat4::identity().m[i][j]).abs() < 1e-12);
+            }
+        }
+
+        let p = Point3::new(1., 2., 3.);
+        assert_close(inv.transform_point(&a.transform_point(&p)), p);
+        assert!(Mat4::new([[0.; 4]; 4]).inverse().is_none());
+    }
+}
@@ -0,0 +1,265 @@
+//! Triangles, alone or in meshes sharing their vertices
+
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::image::Color;
+use crate::interval::Interval;
+use crate::material::{Lambertian, Material};
+use crate::math::Point3;
+use crate::quad::MIN_BOX_SIZE;
+use crate::ray::Ray;
+
+use std::sync::Arc;
+
+/// Möller-Trumbore intersection of the ray with the triangle `abc`, returns
+/// the ray parameter and the barycentric coordinates of `b` and `c`
+fn intersect(
+    ray: &Ray,
+    bounds: &Interval,
+    a: &Point3,
+    b: &Point3,
+    c: &Point3,
+) -> Option<(f64, f64, f64)> {
+    let edge1 = *b - *a;
+    let edge2 = *c - *a;
+
+    let p = ray.direction().cross(&edge2);
+    let det = edge1.dot(&p);
+    // parallel to the plane of the triangle
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+
+    let s = *ray.origin() - *a;
+    let beta = s.dot(&p) * inv_det;
+    if !(0.0..=1.0).contains(&beta) {
+        return None;
+    }
+
+    let q = s.cross(&edge1);
+    let gamma = ray.direction().dot(&q) * inv_det;
+    if gamma < 0.0 || beta + gamma > 1.0 {
+        return None;
+    }
+
+    let t = edge2.dot(&q) * inv_det;
+    bounds.surrounds(t).then_some((t, beta, gamma))
+}
+
+fn hit_record(
+    ray: &Ray,
+    (t, beta, gamma): (f64, f64, f64),
+    a: &Point3,
+    b: &Point3,
+    c: &Point3,
+    material: &Arc<dyn Material>,
+) -> HitRecord {
+    let edge1 = *b - *a;
+    let normal = edge1.cross(&(*c - *a)).normal();
+    let mut rec = HitRecord::new(ray.at(t), normal, t, ray, material.clone());
+    (rec.u, rec.v) = (beta, gamma);
+    rec.set_tangent(&edge1);
+    rec
+}
+
+/// Triangle with vertices `a`, `b` and `c`, its front face is the one where
+/// they are seen counterclockwise
+pub struct Triangle {
+    pub a: Point3,
+    pub b: Point3,
+    pub c: Point3,
+    pub material: Arc<dyn Material>,
+}
+
+impl Triangle {
+    /// Creates a triangle with a gray diffuse material
+    pub fn new(a: Point3, b: Point3, c: Point3) -> Self {
+        Self {
+            a,
+            b,
+            c,
+            material: Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+        }
+    }
+
+    pub fn with_material<M: Material + 'static>(mut self, material: M) -> Self {
+        self.material = Arc::new(material);
+        self
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, ray: &Ray, bounds: Interval) -> Option<HitRecord> {
+        let hit = intersect(ray, &bounds, &self.a, &self.b, &self.c)?;
+        Some(hit_record(
+            ray,
+            hit,
+            &self.a,
+            &self.b,
+            &self.c,
+            &self.material,
+        ))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        Aabb::from_points(self.a, self.b)
+            .union(&Aabb::from_points(self.c, self.c))
+            .pad(MIN_BOX_SIZE)
+    }
+
+    fn triangle_count(&self) -> usize {
+        1
+    }
+}
+
+/// Triangles stored as indices into a vertex buffer, hit in a single loop
+/// without a boxed object per triangle. Meshes made from the same buffer
+/// share it.
+pub struct TriangleMesh {
+    pub vertices: Arc<[Point3]>,
+    pub triangles: Vec<[u32; 3]>,
+    pub material: Arc<dyn Material>,
+    bbox: Aabb,
+}
+
+impl TriangleMesh {
+    /// Creates a mesh with a gray diffuse material, fails if a triangle
+    /// refers to a missing vertex
+    pub fn new(
+        vertices: impl Into<Arc<[Point3]>>,
+        triangles: Vec<[u32; 3]>,
+    ) -> Result<Self, String> {
+        let vertices = vertices.into();
+        let mut bbox = Aabb::empty();
+        for (i, triangle) in triangles.iter().enumerate() {
+            for &index in triangle {
+                let vertex = vertices.get(index as usize).ok_or_else(|| {
+                    format!("Triangle {} refers to the missing vertex {}", i, index)
+                })?;
+                bbox = bbox.union(&Aabb::from_points(*vertex, *vertex));
+            }
+        }
+
+        Ok(Self {
+            vertices,
+            triangles,
+            material: Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+            bbox: bbox.pad(MIN_BOX_SIZE),
+        })
+    }
+
+    pub fn with_material<M: Material + 'static>(mut self, material: M) -> Self {
+        self.material = Arc::new(material);
+        self
+    }
+
+    fn corners(&self, triangle: &[u32; 3]) -> [&Point3; 3] {
+        triangle.map(|i| &self.vertices[i as usize])
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(&self, ray: &Ray, bounds: Interval) -> Option<HitRecord> {
+        if !self.bbox.hit(ray, bounds) {
+            return None;
+        }
+
+        let mut closest = bounds;
+        let mut nearest = None;
+        for triangle in &self.triangles {
+            let [a, b, c] = self.corners(triangle);
+            if let Some(hit) = intersect(ray, &closest, a, b, c) {
+                closest.max = hit.0;
+                nearest = Some((hit, triangle));
+            }
+        }
+
+        let (hit, triangle) = nearest?;
+        let [a, b, c] = self.corners(triangle);
+        Some(hit_record(ray, hit, a, b, c, &self.material))
+    }
+
+    fn hit_any(&self, ray: &Ray, bounds: Interval) -> bool {
+        self.bbox.hit(ray, bounds)
+            && self.triangles.iter().any(|triangle| {
+                let [a, b, c] = self.corners(triangle);
+                intersect(ray, &bounds, a, b, c).is_some()
+            })
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn triangle_count(&self) -> usize {
+        self.triangles.len()
+    }
+}
+
+#[cfg(test)]
+mod mesh_tests {
+    use super::*;
+    use crate::hittable::HittableList;
+    use crate::math::Vec3;
+
+    fn cube() -> (Vec<Point3>, Vec<[u32; 3]>) {
+        let vertices = (0..8)
+            .map(|i| {
+                let coord = |bit: u32| if i & bit == 0 { -1.0 } else { 1.0 };
+                Point3::new(coord(1), coord(2), coord(4))
+            })
+            .collect();
+        // two counterclockwise triangles per face, seen from outside
+        let faces = [
+            [0, 2, 3, 1], // -z
+            [4, 5, 7, 6], // +z
+            [0, 1, 5, 4], // -y
+            [2, 6, 7, 3], // +y
+            [0, 4, 6, 2], // -x
+            [1, 3, 7, 5], // +x
+        ];
+        let triangles = faces
+            .iter()
+            .flat_map(|[a, b, c, d]| [[*a, *b, *c], [*a, *c, *d]])
+            .collect();
+        (vertices, triangles)
+    }
+
+    #[test]
+    fn mesh_matches_triangles() {
+        let (vertices, triangles) = cube();
+        let mut list = HittableList::new();
+        for [a, b, c] in &triangles {
+            let corner = |i: &u32| vertices[*i as usize];
+            list.add(Triangle::new(corner(a), corner(b), corner(c)));
+        }
+        let mesh = TriangleMesh::new(vertices, triangles).unwrap();
+        assert_eq!(mesh.bounding_box().max(), Point3::new(1., 1., 1.));
+        assert_eq!(mesh.triangle_count(), 12);
+        assert_eq!(list.triangle_count(), 12);
+
+        let mut hits = 0;
+        for i in 0..20 {
+            for j in 0..20 {
+                let origin = Point3::new(0.3, -0.2, 5.);
+                let dir = Vec3::new(i as f64 / 8. - 1.2, j as f64 / 8. - 1.2, -4.);
+                let ray = Ray::new(origin, dir);
+
+                let expected = list.hit(&ray, Interval::positive());
+                let rec = mesh.hit(&ray, Interval::positive());
+                assert_eq!(rec.is_some(), expected.is_some());
+                assert_eq!(mesh.hit_any(&ray, Interval::positive()), expected.is_some());
+                if let (Some(rec), Some(expected)) = (rec, expected) {
+                    assert_eq!(rec.t, expected.t);
+                    assert_eq!(rec.normal, expected.normal);
+                    assert_eq!(rec.front_face, Some(true));
+                    hits += 1;
+                }
+            }
+        }
+        assert!(hits > 50);
+
+        assert!(TriangleMesh::new(vec![Point3::zero()], vec![[0, 0, 1]]).is_err());
+    }
+}
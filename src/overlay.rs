@@ -0,0 +1,55 @@
+//! 2D drawing on top of rendered buffers, for debugging views
+
+use crate::image::{Color, Image};
+use crate::math::lerp;
+
+/// Draws an anti-aliased segment about one pixel wide between two points in
+/// continuous pixel coordinates, where the center of the pixel `(u, v)` is
+/// at `(u, v)`. Each pixel is blended toward `color` by its distance from the
+/// segment.
+pub fn draw_line(buffer: &mut [Color], img: &Image, a: (f64, f64), b: (f64, f64), color: &Color) {
+    let u_min = (a.0.min(b.0).floor() as i32 - 1).max(0);
+    let u_max = (a.0.max(b.0).ceil() as i32 + 1).min(img.width - 1);
+    let v_min = (a.1.min(b.1).floor() as i32 - 1).max(0);
+    let v_max = (a.1.max(b.1).ceil() as i32 + 1).min(img.height - 1);
+
+    for v in v_min..=v_max {
+        for u in u_min..=u_max {
+            let coverage = 1.0 - distance_to_segment((u as f64, v as f64), a, b);
+            if coverage > 0.0 {
+                let pixel = &mut buffer[(v * img.width + u) as usize];
+                *pixel = lerp(pixel, color, coverage);
+            }
+        }
+    }
+}
+
+fn distance_to_segment(p: (f64, f64), a: (f64, f64), b: (f64, f64)) -> f64 {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let len2 = dx * dx + dy * dy;
+    let t = if len2 > 0.0 {
+        (((p.0 - a.0) * dx + (p.1 - a.1) * dy) / len2).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    let (cx, cy) = (a.0 + t * dx - p.0, a.1 + t * dy - p.1);
+    (cx * cx + cy * cy).sqrt()
+}
+
+#[cfg(test)]
+mod overlay_tests {
+    use super::*;
+
+    #[test]
+    fn horizontal_line() {
+        let img = Image::new(8, 5).unwrap();
+        let mut buffer = vec![Color::zero(); 40];
+        let red = Color::new(1., 0., 0.);
+
+        draw_line(&mut buffer, &img, (1., 2.), (6., 2.), &red);
+
+        assert_eq!(buffer[2 * 8 + 3], red);
+        assert_eq!(buffer[2 * 8 + 7], Color::zero());
+        assert_eq!(buffer[8 + 3], Color::zero());
+    }
+}
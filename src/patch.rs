@@ -0,0 +1,214 @@
+//! Bilinear patch, the simplest curved surface: the quadrilateral spanned by
+//! four corners that don't need to lie on a plane
+
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::image::Color;
+use crate::interval::Interval;
+use crate::material::{Lambertian, Material};
+use crate::math::{Point3, Vec3};
+use crate::quad::MIN_BOX_SIZE;
+use crate::ray::Ray;
+
+use std::sync::Arc;
+
+/// Surface `P(s, t) = p00 (1-s)(1-t) + p10 s(1-t) + p01 (1-s)t + p11 st` for
+/// `s` and `t` in `[0, 1]`
+pub struct BilinearPatch {
+    pub p00: Point3,
+    pub p10: Point3,
+    pub p01: Point3,
+    pub p11: Point3,
+    pub material: Arc<dyn Material>,
+}
+
+impl BilinearPatch {
+    /// Creates a patch with a gray diffuse material
+    pub fn new(p00: Point3, p10: Point3, p01: Point3, p11: Point3) -> Self {
+        Self {
+            p00,
+            p10,
+            p01,
+            p11,
+            material: Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+        }
+    }
+
+    pub fn with_material<M: Material + 'static>(mut self, material: M) -> Self {
+        self.material = Arc::new(material);
+        self
+    }
+
+    pub fn at(&self, s: f64, t: f64) -> Point3 {
+        let (b, c, a) = self.coefficients();
+        self.p00 + s * b + t * c + (s * t) * a
+    }
+
+    /// `b`, `c` and `a` of `P(s, t) = p00 + s b + t c + st a`
+    fn coefficients(&self) -> (Vec3, Vec3, Vec3) {
+        let b = self.p10 - self.p00;
+        let c = self.p01 - self.p00;
+        let a = self.p11 - self.p10 - self.p01 + self.p00;
+        (b, c, a)
+    }
+
+    /// Parameters `(s, t)` of the points of the patch crossed by the line of
+    /// the ray
+    fn intersect_st(&self, ray: &Ray) -> Vec<(f64, f64)> {
+        let (b, c, a) = self.coefficients();
+        let dir = ray.direction();
+        let d = self.p00 - *ray.origin();
+
+        // two planes containing the ray, P(s, t) must lie on both
+        let helper = if dir.x().abs() < 0.9 * dir.length() {
+            Vec3::new(1., 0., 0.)
+        } else {
+            Vec3::new(0., 1., 0.)
+        };
+        let e1 = dir.cross(&helper);
+        let e2 = dir.cross(&e1);
+
+        // A st + B s + C t + D = 0 on each plane
+        let eq = |e: &Vec3| (a.dot(e), b.dot(e), c.dot(e), d.dot(e));
+        let (a1, b1, c1, d1) = eq(&e1);
+        let (a2, b2, c2, d2) = eq(&e2);
+
+        // eliminating t leaves a quadratic in s
+        let qa = b1 * a2 - b2 * a1;
+        let qb = b1 * c2 + d1 * a2 - b2 * c1 - d2 * a1;
+        let qc = d1 * c2 - d2 * c1;
+
+        let roots = if qa.abs() < 1e-12 * (qb.abs() + qc.abs()).max(1e-300) {
+            if qb == 0.0 { vec![] } else { vec![-qc / qb] }
+        } else {
+            let discriminant = qb * qb - 4.0 * qa * qc;
+            if discriminant < 0.0 {
+                return vec![];
+            }
+            let sqrtd = discriminant.sqrt();
+            // numerically stable form of the two roots
+            let q = -0.5 * (qb + qb.signum() * sqrtd);
+            if q == 0.0 {
+                vec![0.0]
+            } else {
+                vec![q / qa, qc / q]
+            }
+        };
+
+        let unit = Interval::new(0.0, 1.0);
+        roots
+            .into_iter()
+            .filter(|s| unit.contains(*s))
+            .filter_map(|s| {
+                let (den1, den2) = (a1 * s + c1, a2 * s + c2);
+                let t = if den1.abs() > den2.abs() {
+                    -(b1 * s + d1) / den1
+                } else {
+                    -(b2 * s + d2) / den2
+                };
+                t.is_finite().then_some((s, t))
+            })
+            .filter(|(_, t)| unit.contains(*t))
+            .collect()
+    }
+}
+
+impl Hittable for BilinearPatch {
+    fn hit(&self, ray: &Ray, bounds: Interval) -> Option<HitRecord> {
+        let dir = ray.direction();
+
+        let (s, t, ray_t) = self
+            .intersect_st(ray)
+            .into_iter()
+            .map(|(s, t)| {
+                (
+                    s,
+                    t,
+                    (self.at(s, t) - *ray.origin()).dot(dir) / dir.length2(),
+                )
+            })
+            .filter(|(_, _, ray_t)| bounds.surrounds(*ray_t))
+            .min_by(|x, y| x.2.total_cmp(&y.2))?;
+
+        let (b, c, a) = self.coefficients();
+        let dp_ds = b + t * a;
+        let dp_dt = c + s * a;
+        let outward_normal = dp_ds.cross(&dp_dt).normal();
+
+        let mut rec = HitRecord::new(
+            ray.at(ray_t),
+            outward_normal,
+            ray_t,
+            ray,
+            self.material.clone(),
+        );
+        (rec.u, rec.v) = (s, t);
+        rec.set_tangent(&dp_ds);
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        Aabb::from_points(self.p00, self.p11)
+            .union(&Aabb::from_points(self.p10, self.p01))
+            .pad(MIN_BOX_SIZE)
+    }
+}
+
+#[cfg(test)]
+mod patch_tests {
+    use super::*;
+    use crate::quad::Quad;
+
+    #[test]
+    fn planar_patch_is_a_quad() {
+        let (p00, p10) = (Point3::new(-1., -1., -2.), Point3::new(1., -1., -2.));
+        let (p01, p11) = (Point3::new(-1., 1., -2.), Point3::new(1., 1., -2.));
+        let patch = BilinearPatch::new(p00, p10, p01, p11);
+        let quad = Quad::new(p00, p10 - p00, p01 - p00);
+
+        for i in 0..15 {
+            for j in 0..15 {
+                let dir = Vec3::new(i as f64 / 7.0 - 1.0, j as f64 / 7.0 - 1.0, -1.0);
+                let ray = Ray::new(Point3::new(0., 0., 0.5), dir);
+                let expected = quad.hit(&ray, Interval::positive());
+                let rec = patch.hit(&ray, Interval::positive());
+                assert_eq!(rec.is_some(), expected.is_some(), "ray {:?}", dir);
+
+                if let (Some(rec), Some(expected)) = (rec, expected) {
+                    assert!((rec.t - expected.t).abs() < 1e-9);
+                    assert!(rec.normal.approx_eq(&expected.normal, 1e-9));
+                    assert!((rec.u - expected.u).abs() < 1e-9);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn warped_patch() {
+        // saddle z = x y over the unit square
+        let patch = BilinearPatch::new(
+            Point3::new(0., 0., 0.),
+            Point3::new(1., 0., 0.),
+            Point3::new(0., 1., 0.),
+            Point3::new(1., 1., 1.),
+        );
+
+        for i in 1..10 {
+            let x = i as f64 / 10.0;
+            let y = 1.0 - x * 0.5;
+            let ray = Ray::new(Point3::new(x, y, 5.), Vec3::new(0., 0., -1.));
+            let rec = patch.hit(&ray, Interval::positive()).unwrap();
+            assert!((rec.point.z() - x * y).abs() < 1e-9);
+        }
+
+        // along the diagonal the surface follows the curve z = x^2
+        let along = Ray::new(Point3::new(0.5, 0.5, 5.), Vec3::new(0., 0., -1.));
+        let rec = patch.hit(&along, Interval::positive()).unwrap();
+        assert!((rec.point.z() - 0.25).abs() < 1e-9);
+        let expected = Vec3::new(-0.5, -0.5, 1.).normal();
+        assert!(rec.normal.approx_eq(&expected, 1e-9));
+
+        let outside = Ray::new(Point3::new(1.5, 0.5, 5.), Vec3::new(0., 0., -1.));
+        assert!(patch.hit(&outside, Interval::positive()).is_none());
+    }
+}
@@ -0,0 +1,134 @@
+//! Perlin gradient noise, the base of the procedural textures
+
+use crate::math::{Point3, Vec3};
+
+use rand::rngs::SmallRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+const POINT_COUNT: usize = 256;
+
+/// Smooth noise made of random unit gradients on the lattice points,
+/// interpolated with a Hermite cubic
+#[derive(Debug, Clone)]
+pub struct Perlin {
+    gradients: Vec<Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Perlin {
+    /// The noise only depends on `seed`
+    pub fn new(seed: u64) -> Self {
+        let mut rng = SmallRng::seed_from_u64(seed);
+        let gradients = (0..POINT_COUNT)
+            .map(|_| {
+                let v = Vec3::new(
+                    rng.random_range(-1.0..1.0),
+                    rng.random_range(-1.0..1.0),
+                    rng.random_range(-1.0..1.0),
+                );
+                v.normal()
+            })
+            .collect();
+
+        let permutation = |rng: &mut SmallRng| {
+            let mut p: Vec<usize> = (0..POINT_COUNT).collect();
+            p.shuffle(rng);
+            p
+        };
+        Self {
+            gradients,
+            perm_x: permutation(&mut rng),
+            perm_y: permutation(&mut rng),
+            perm_z: permutation(&mut rng),
+        }
+    }
+
+    /// Noise value at `p`, in `[-1, 1]`
+    pub fn noise(&self, p: &Point3) -> f64 {
+        let floor = [p.x().floor(), p.y().floor(), p.z().floor()];
+        let frac = [p.x() - floor[0], p.y() - floor[1], p.z() - floor[2]];
+        let cell = floor.map(|f| f as i64);
+
+        let mut corners = [[[Vec3::zero(); 2]; 2]; 2];
+        for (di, plane) in corners.iter_mut().enumerate() {
+            for (dj, row) in plane.iter_mut().enumerate() {
+                for (dk, corner) in row.iter_mut().enumerate() {
+                    let index = self.perm_x[lattice(cell[0] + di as i64)]
+                        ^ self.perm_y[lattice(cell[1] + dj as i64)]
+                        ^ self.perm_z[lattice(cell[2] + dk as i64)];
+                    *corner = self.gradients[index];
+                }
+            }
+        }
+
+        trilinear(&corners, frac)
+    }
+
+    /// Sum of `|noise|` over `depth` octaves, each at twice the frequency and
+    /// half the weight of the previous one. The result is in `[0, 2)`.
+    pub fn turbulence(&self, p: &Point3, depth: u32) -> f64 {
+        let mut sum = 0.0;
+        let mut point = *p;
+        let mut weight = 1.0;
+
+        for _ in 0..depth {
+            sum += weight * self.noise(&point).abs();
+            weight *= 0.5;
+            point *= 2.0;
+        }
+
+        sum
+    }
+}
+
+fn lattice(i: i64) -> usize {
+    (i & (POINT_COUNT as i64 - 1)) as usize
+}
+
+/// Interpolates the dot products of the corner gradients with the offsets of
+/// the point from the corners
+fn trilinear(corners: &[[[Vec3; 2]; 2]; 2], frac: [f64; 3]) -> f64 {
+    let smooth = frac.map(|f| f * f * (3.0 - 2.0 * f));
+
+    let mut sum = 0.0;
+    for (i, plane) in corners.iter().enumerate() {
+        for (j, row) in plane.iter().enumerate() {
+            for (k, gradient) in row.iter().enumerate() {
+                let (fi, fj, fk) = (i as f64, j as f64, k as f64);
+                let offset = Vec3::new(frac[0] - fi, frac[1] - fj, frac[2] - fk);
+                sum += (fi * smooth[0] + (1.0 - fi) * (1.0 - smooth[0]))
+                    * (fj * smooth[1] + (1.0 - fj) * (1.0 - smooth[1]))
+                    * (fk * smooth[2] + (1.0 - fk) * (1.0 - smooth[2]))
+                    * gradient.dot(&offset);
+            }
+        }
+    }
+
+    sum
+}
+
+#[cfg(test)]
+mod perlin_tests {
+    use super::*;
+
+    #[test]
+    fn turbulence_range() {
+        let perlin = Perlin::new(42);
+        for i in 0..500 {
+            let t = i as f64 * 0.37;
+            let p = Point3::new(t.sin() * 10.0, t, (0.5 * t).cos() * 3.0);
+            let noise = perlin.noise(&p);
+            assert!((-1.0..=1.0).contains(&noise));
+
+            let turbulence = perlin.turbulence(&p, 7);
+            assert!((0.0..2.0).contains(&turbulence));
+        }
+
+        // smooth noise is zero on the lattice points
+        assert_eq!(perlin.noise(&Point3::new(3., -2., 5.)), 0.0);
+        assert_eq!(perlin.turbulence(&Point3::new(0.3, 0.1, 0.2), 0), 0.0);
+    }
+}
@@ -0,0 +1,183 @@
+//! Minimal PNG encoder, the pixel data is stored uncompressed in the zlib
+//! stream so no compression library is needed.
+
+use crate::camera::{Pixel, ToneMap};
+use crate::image::Image;
+
+use std::io;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+/// Largest payload of a stored deflate block
+const MAX_STORED_BLOCK: usize = 65535;
+
+/// Color marking the clipped pixels, see [`write_rgba_with`]
+pub const CLIP_MAGENTA: [u8; 3] = [255, 0, 255];
+
+/// Writes the pixels as an 8-bit RGBA PNG. Colors get the same tone mapping
+/// and clamping as the PPM output, alpha is the coverage of each pixel.
+pub fn write_rgba(
+    w: &mut impl io::Write,
+    img: &Image,
+    pixels: &[Pixel],
+    tone_map: ToneMap,
+) -> io::Result<()> {
+    write_rgba_with(w, img, pixels, tone_map, None)
+}
+
+/// Same as [`write_rgba`], with the pixels having a channel over 1 after
+/// the tone mapping painted with `clip_marker` if set, to spot the clipped
+/// areas of an image
+pub fn write_rgba_with(
+    w: &mut impl io::Write,
+    img: &Image,
+    pixels: &[Pixel],
+    tone_map: ToneMap,
+    clip_marker: Option<[u8; 3]>,
+) -> io::Result<()> {
+    // Every scanline starts with its filter type, 0 means no filter
+    let mut raw = Vec::with_capacity(pixels.len() * 4 + img.height as usize);
+    for row in pixels.chunks(img.width as usize) {
+        raw.push(0);
+        for p in row {
+            let clipped = p.color.components().any(|c| tone_map.apply(c) > 1.0);
+            match clip_marker {
+                Some(marker) if clipped => raw.extend_from_slice(&marker),
+                _ => raw.extend_from_slice(&p.color.to_rgb8(tone_map)),
+            }
+            raw.push((255.0 * p.alpha.clamp(0.0, 1.0)).round() as u8);
+        }
+    }
+
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&(img.width as u32).to_be_bytes());
+    ihdr.extend_from_slice(&(img.height as u32).to_be_bytes());
+    // bit depth 8, color type 6 (RGBA), deflate, adaptive filtering, no interlace
+    ihdr.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+    w.write_all(&SIGNATURE)?;
+    write_chunk(w, b"IHDR", &ihdr)?;
+    write_chunk(w, b"IDAT", &zlib_stored(&raw))?;
+    write_chunk(w, b"IEND", &[])?;
+    Ok(())
+}
+
+fn write_chunk(w: &mut impl io::Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    w.write_all(&(data.len() as u32).to_be_bytes())?;
+    w.write_all(kind)?;
+    w.write_all(data)?;
+
+    let crc = crc32(&[kind.as_slice(), data].concat());
+    w.write_all(&crc.to_be_bytes())?;
+    Ok(())
+}
+
+/// Wraps the data in a zlib stream made of uncompressed deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+
+    let mut blocks = data.chunks(MAX_STORED_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let len = block.len() as u16;
+        out.push(last as u8);
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffffffffu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb88320 & mask);
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod png_tests {
+    use super::*;
+    use crate::image::Color;
+
+    #[test]
+    fn checksums() {
+        assert_eq!(crc32(b"IEND"), 0xae426082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+    }
+
+    #[test]
+    fn rgba_layout() {
+        let img = Image::new(2, 1).unwrap();
+        let pixels = [
+            Pixel {
+                color: Color::new(1.0, 1.0, 1.0),
+                alpha: 1.0,
+            },
+            Pixel {
+                color: Color::zero(),
+                alpha: 0.0,
+            },
+        ];
+
+        let mut out = Vec::new();
+        write_rgba(&mut out, &img, &pixels, ToneMap::Gamma2).unwrap();
+
+        assert_eq!(out[..8], SIGNATURE);
+        assert_eq!(&out[12..16], b"IHDR");
+        assert_eq!(out[16..24], [0, 0, 0, 2, 0, 0, 0, 1]);
+        assert_eq!(out[24..26], [8, 6]);
+
+        // IDAT: zlib header, one stored block holding the filter byte and
+        // the two pixels
+        let idat = &out[33 + 8..];
+        assert_eq!(idat[..2], [0x78, 0x01]);
+        assert_eq!(idat[2..7], [1, 9, 0, !9, 0xff]);
+        assert_eq!(idat[7..16], [0, 254, 254, 254, 255, 0, 0, 0, 0]);
+        assert_eq!(&out[out.len() - 8..out.len() - 4], b"IEND");
+    }
+
+    #[test]
+    fn clipped_marker() {
+        let img = Image::new(2, 1).unwrap();
+        let pixel = |r: f64| Pixel {
+            color: Color::new(r, 0.5, 0.5),
+            alpha: 1.0,
+        };
+        let pixels = [pixel(2.0), pixel(0.5)];
+        // RGB of the two pixels in the only stored block
+        let rgb = |clip_marker: Option<[u8; 3]>| {
+            let mut out = Vec::new();
+            write_rgba_with(&mut out, &img, &pixels, ToneMap::None, clip_marker).unwrap();
+            let idat = &out[33 + 8 + 7..];
+            (idat[1..4].to_vec(), idat[5..8].to_vec())
+        };
+
+        let (over, normal) = rgb(Some(CLIP_MAGENTA));
+        assert_eq!(over, CLIP_MAGENTA);
+        assert_eq!(normal, [127, 127, 127]);
+
+        let (over, normal) = rgb(None);
+        assert_eq!(over, [254, 127, 127]);
+        assert_eq!(normal, [127, 127, 127]);
+    }
+}
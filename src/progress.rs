@@ -0,0 +1,168 @@
+//! Progress report of the multithreaded renders
+
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Minimum time between two reports
+const REPORT_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Receives the progress of the renders, see [`crate::camera::Camera::progress`]
+pub type ProgressCallback = Arc<dyn Fn(&ProgressReport) + Send + Sync>;
+
+/// Completed pixels of a render at some point in time. Displayed as a
+/// progress line with the percentage done and the estimated time left.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProgressReport {
+    pub done: usize,
+    pub total: usize,
+    pub elapsed: Duration,
+    /// Last report of the render
+    pub finished: bool,
+}
+
+impl ProgressReport {
+    /// Fraction of the pixels completed, in `[0, 1]`
+    pub fn fraction(&self) -> f64 {
+        if self.total > 0 {
+            self.done as f64 / self.total as f64
+        } else {
+            1.0
+        }
+    }
+
+    /// Time left, assuming the remaining pixels take as long as the
+    /// completed ones. None before the first pixel completes.
+    pub fn eta(&self) -> Option<Duration> {
+        if self.done == 0 {
+            return None;
+        }
+        let fraction = self.fraction();
+        Some(self.elapsed.mul_f64((1.0 - fraction).max(0.0) / fraction))
+    }
+}
+
+impl fmt::Display for ProgressReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let eta = match self.eta() {
+            Some(eta) => format!("{:.0}s", eta.as_secs_f64()),
+            None => "?".to_string(),
+        };
+        write!(
+            f,
+            "Rendering {:5.1}% ETA {:<6}",
+            100.0 * self.fraction(),
+            eta
+        )
+    }
+}
+
+/// Counter of the completed pixels shared by the render threads, reporting
+/// them to the callback at most every `REPORT_INTERVAL`. The estimate only
+/// depends on the count, so it is correct whatever the order in which the
+/// pixels complete.
+pub struct Progress {
+    total: usize,
+    done: AtomicUsize,
+    start: Instant,
+    callback: Option<ProgressCallback>,
+    /// Time of the last report
+    last_report: Mutex<Instant>,
+}
+
+impl Progress {
+    pub fn new(total: usize, callback: Option<ProgressCallback>) -> Self {
+        let start = Instant::now();
+        Self {
+            total,
+            done: AtomicUsize::new(0),
+            start,
+            callback,
+            last_report: Mutex::new(start),
+        }
+    }
+
+    /// Records `pixels` more completed pixels, reporting the progress if the
+    /// last report is older than `REPORT_INTERVAL`
+    pub fn add(&self, pixels: usize) {
+        let done = self.done.fetch_add(pixels, Ordering::Relaxed) + pixels;
+        let Some(callback) = &self.callback else {
+            return;
+        };
+
+        // another thread reporting is as good as this one reporting
+        let Ok(mut last_report) = self.last_report.try_lock() else {
+            return;
+        };
+        if last_report.elapsed() >= REPORT_INTERVAL {
+            *last_report = Instant::now();
+            callback(&self.report(done, false));
+        }
+    }
+
+    /// Number of completed pixels
+    pub fn done(&self) -> usize {
+        self.done.load(Ordering::Relaxed)
+    }
+
+    /// Sends the final report
+    pub fn finish(&self) {
+        if let Some(callback) = &self.callback {
+            callback(&self.report(self.done(), true));
+        }
+    }
+
+    fn report(&self, done: usize, finished: bool) -> ProgressReport {
+        ProgressReport {
+            done,
+            total: self.total,
+            elapsed: self.start.elapsed(),
+            finished,
+        }
+    }
+}
+
+#[cfg(test)]
+mod progress_tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn concurrent_counts() {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let sink = reports.clone();
+        let callback: ProgressCallback = Arc::new(move |r| sink.lock().unwrap().push(*r));
+        let progress = Progress::new(8 * 1000, Some(callback));
+        thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| (0..1000).for_each(|_| progress.add(1)));
+            }
+        });
+        assert_eq!(progress.done(), 8000);
+
+        progress.finish();
+        let last = *reports.lock().unwrap().last().unwrap();
+        assert!(last.finished);
+        assert_eq!((last.done, last.total), (8000, 8000));
+
+        // without a callback the pixels are only counted
+        let silent = Progress::new(10, None);
+        silent.add(4);
+        silent.finish();
+        assert_eq!(silent.done(), 4);
+    }
+
+    #[test]
+    fn eta() {
+        let report = |done, secs| ProgressReport {
+            done,
+            total: 100,
+            elapsed: Duration::from_secs(secs),
+            finished: false,
+        };
+        assert_eq!(report(25, 10).to_string(), "Rendering  25.0% ETA 30s   ");
+        assert!(report(0, 0).to_string().contains("ETA ?"));
+        assert!(report(100, 10).to_string().contains("100.0% ETA 0s"));
+    }
+}
@@ -0,0 +1,201 @@
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::image::Color;
+use crate::interval::Interval;
+use crate::light::{Light, QuadLight};
+use crate::material::{Lambertian, Material};
+use crate::math::{Point3, UnitVec3, Vec3};
+use crate::ray::Ray;
+
+use std::sync::Arc;
+
+/// Minimum thickness of the bounding box along each axis, so that the slab
+/// test works for quads lying on an axis plane
+pub(crate) const MIN_BOX_SIZE: f64 = 1e-4;
+
+/// Parallelogram with a corner in `q` and sides `u` and `v`
+#[derive(Clone)]
+pub struct Quad {
+    pub q: Point3,
+    pub u: Vec3,
+    pub v: Vec3,
+    pub material: Arc<dyn Material>,
+
+    /// Unit normal of the plane, `u x v` normalized. None for a quad of zero
+    /// area, which is never hit.
+    normal: Option<UnitVec3>,
+    /// Plane constant, `normal . q`
+    d: f64,
+    /// Vector used to find the plane coordinates of a point, `n / (n . n)`
+    /// with `n = u x v`
+    w: Vec3,
+}
+
+impl Quad {
+    /// Creates a quad with a gray diffuse material. Parallel or zero sides
+    /// give a quad of zero area, invisible.
+    pub fn new(q: Point3, u: Vec3, v: Vec3) -> Self {
+        let n = u.cross(&v);
+        let normal = (n.length2() > 0.0).then(|| UnitVec3::new(n));
+        Self {
+            q,
+            u,
+            v,
+            material: Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+            normal,
+            d: normal.map_or(0.0, |normal| normal.dot(&q)),
+            w: normal.map_or(Vec3::zero(), |_| n / n.length2()),
+        }
+    }
+
+    pub fn with_material<M: Material + 'static>(mut self, material: M) -> Self {
+        self.material = Arc::new(material);
+        self
+    }
+
+    /// Unit normal of the quad, zero if its area is zero
+    pub fn normal(&self) -> Vec3 {
+        self.normal.map_or(Vec3::zero(), |normal| normal.get())
+    }
+
+    /// Checks if the quad has an emissive material and an area to emit from
+    pub fn is_emissive(&self) -> bool {
+        self.normal.is_some() && self.material.is_emissive()
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, ray: &Ray, bounds: Interval) -> Option<HitRecord> {
+        let normal = self.normal?;
+        let denom = normal.dot(ray.direction());
+        // parallel to the plane
+        if denom.abs() < 1e-8 {
+            return None;
+        }
+
+        let t = (self.d - normal.dot(ray.origin())) / denom;
+        if !bounds.surrounds(t) {
+            return None;
+        }
+
+        // coordinates of the hit point along the sides
+        let point = ray.at(t);
+        let p = point - self.q;
+        let alpha = self.w.dot(&p.cross(&self.v));
+        let beta = self.w.dot(&self.u.cross(&p));
+        let unit = Interval::new(0.0, 1.0);
+        if !unit.contains(alpha) || !unit.contains(beta) {
+            return None;
+        }
+
+        let mut rec = HitRecord::new(point, self.normal(), t, ray, self.material.clone());
+        (rec.u, rec.v) = (alpha, beta);
+        rec.set_tangent(&self.u);
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let diagonals = Aabb::from_points(self.q, self.q + self.u + self.v)
+            .union(&Aabb::from_points(self.q + self.u, self.q + self.v));
+        diagonals.pad(MIN_BOX_SIZE)
+    }
+
+    fn lights(&self) -> Vec<Arc<dyn Light>> {
+        if self.is_emissive() {
+            vec![Arc::new(QuadLight::new(self.clone()))]
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+/// Flat square ground, an horizontal [`Quad`] facing up
+pub struct Ground {
+    pub quad: Quad,
+}
+
+impl Ground {
+    /// Ground at height `y` extending `half_size` from the origin along x and z
+    pub fn new(y: f64, half_size: f64) -> Self {
+        let corner = Point3::new(-half_size, y, -half_size);
+        let size = 2.0 * half_size;
+        Self {
+            quad: Quad::new(corner, Vec3::new(0., 0., size), Vec3::new(size, 0., 0.)),
+        }
+    }
+
+    pub fn with_material<M: Material + 'static>(mut self, material: M) -> Self {
+        self.quad = self.quad.with_material(material);
+        self
+    }
+}
+
+impl Hittable for Ground {
+    fn hit(&self, ray: &Ray, bounds: Interval) -> Option<HitRecord> {
+        self.quad.hit(ray, bounds)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.quad.bounding_box()
+    }
+
+    fn lights(&self) -> Vec<Arc<dyn Light>> {
+        self.quad.lights()
+    }
+}
+
+#[cfg(test)]
+mod quad_tests {
+    use super::*;
+    use crate::material::DiffuseLight;
+
+    #[test]
+    fn zero_area() {
+        let flat = Quad::new(
+            Point3::new(0., 0., -1.),
+            Vec3::new(1., 0., 0.),
+            Vec3::new(2., 0., 0.),
+        )
+        .with_material(DiffuseLight::new(Color::new(1., 1., 1.), 1.));
+        assert_eq!(flat.normal(), Vec3::zero());
+
+        let ray = Ray::new(Point3::new(0.5, 0., 0.), Vec3::new(0., 0., -1.));
+        assert!(flat.hit(&ray, Interval::positive()).is_none());
+        assert!(flat.lights().is_empty());
+    }
+
+    #[test]
+    fn ground_hit() {
+        let ground = Ground::new(-0.5, 10.0);
+        assert_eq!(ground.quad.normal(), Vec3::new(0., 1., 0.));
+
+        let down = Ray::new(Point3::new(2., 3., -4.), Vec3::new(0., -1., 0.));
+        let rec = ground.hit(&down, Interval::positive()).unwrap();
+        assert_eq!(rec.point.y(), -0.5);
+        assert_eq!(rec.t, 3.5);
+        assert_eq!(rec.front_face, Some(true));
+        assert_eq!((rec.u, rec.v), (0.3, 0.6));
+
+        let outside = Ray::new(Point3::new(11., 3., 0.), Vec3::new(0., -1., 0.));
+        assert!(ground.hit(&outside, Interval::positive()).is_none());
+
+        let bbox = ground.bounding_box();
+        assert!(bbox.y.size() > 0.0);
+        assert!(bbox.hit(&down, Interval::positive()));
+    }
+
+    #[test]
+    fn tangent_frame() {
+        let quad = Quad::new(
+            Point3::new(-1., -1., -2.),
+            Vec3::new(2., 0., 0.5),
+            Vec3::new(0.5, 2., 0.),
+        );
+        for origin in [Point3::new(0.2, 0.1, 3.), Point3::new(0.2, 0.1, -5.)] {
+            let ray = Ray::new(origin, Point3::new(0.1, 0.2, -2.) - origin);
+            let rec = quad.hit(&ray, Interval::positive()).unwrap();
+            rec.assert_tangent_frame();
+            assert!(rec.tangent.approx_eq(&quad.u.normal(), 1e-12));
+        }
+    }
+}
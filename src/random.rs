@@ -1,4 +1,39 @@
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+use std::cell::RefCell;
+
+thread_local! {
+    /// Generator shared by all the sampling functions of the current thread
+    static RNG: RefCell<SmallRng> = RefCell::new(SmallRng::from_os_rng());
+}
+
 /// Returns a random number in the range [0, 1]
 pub fn normal_random() -> f64 {
-    rand::random_range(0.0..1.0)
+    RNG.with(|rng| rng.borrow_mut().random_range(0.0..1.0))
+}
+
+/// Reseeds the generator of the current thread, every following random
+/// number drawn on this thread is fully determined by `seed`
+pub fn seed(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = SmallRng::seed_from_u64(seed));
+}
+
+/// Derives the seed of the pixel `(u, v)` from a global seed, so that the
+/// samples of a pixel do not depend on the order the pixels are rendered
+pub fn pixel_seed(seed: u64, u: i32, v: i32) -> u64 {
+    mix(mix(seed ^ u as u32 as u64) ^ v as u32 as u64)
+}
+
+/// Derives the seed of the sample `index` of the pixel `(u, v)`, so that any
+/// sample can be taken again without taking the previous ones
+pub fn sample_seed(seed: u64, u: i32, v: i32, index: u32) -> u64 {
+    mix(pixel_seed(seed, u, v) ^ index as u64)
+}
+
+/// SplitMix64 finalizer, spreads small input differences over all the bits
+fn mix(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
 }
@@ -1,13 +1,35 @@
 use crate::math::Vec3;
 
+/// Directions of the rays shot through the neighbouring pixels, along the
+/// image x and y axes. They estimate the footprint of a ray on the surfaces
+/// it hits, e.g. to filter textures.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RayDifferentials {
+    pub dx: Vec3,
+    pub dy: Vec3,
+}
+
 pub struct Ray {
     orig: Vec3,
     dir: Vec3,
+    differentials: Option<RayDifferentials>,
 }
 
 impl Ray {
     pub fn new(orig: Vec3, dir: Vec3) -> Self {
-        Self { orig, dir }
+        Self {
+            orig,
+            dir,
+            differentials: None,
+        }
+    }
+
+    pub fn with_differentials(orig: Vec3, dir: Vec3, differentials: RayDifferentials) -> Self {
+        Self {
+            orig,
+            dir,
+            differentials: Some(differentials),
+        }
     }
 
     pub fn origin(&self) -> &Vec3 {
@@ -18,7 +40,23 @@ impl Ray {
         &self.dir
     }
 
+    pub fn differentials(&self) -> Option<&RayDifferentials> {
+        self.differentials.as_ref()
+    }
+
     pub fn at(&self, t: f64) -> Vec3 {
         self.dir * t + self.orig
     }
+
+    /// Approximate size of the area covered by the ray at parameter `t`, as
+    /// the distance from the neighbouring rays along the image x and y axes.
+    /// None if the ray has no differentials.
+    pub fn footprint(&self, t: f64) -> Option<(f64, f64)> {
+        self.differentials.map(|d| {
+            (
+                ((d.dx - self.dir) * t).length(),
+                ((d.dy - self.dir) * t).length(),
+            )
+        })
+    }
 }
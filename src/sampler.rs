@@ -0,0 +1,203 @@
+//! Sources of the 2D points used to jitter the primary rays inside a pixel
+//! and to choose the directions of the material bounces
+
+use crate::math::Vec3;
+use crate::random::normal_random;
+
+use std::cell::Cell;
+use std::f64::consts::PI;
+
+/// Halton bases of the dimensions following the two of the primary jitter,
+/// a pair for each bounce. Deeper bounces fall back to random numbers.
+const BOUNCE_BASES: [u32; 12] = [5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43];
+
+/// Shifts of the bounce dimensions, see [`Sampler::bounce_rotations`]
+pub type BounceRotations = [f64; BOUNCE_BASES.len()];
+
+/// Point of the low-discrepancy sequence the bounces of the current path
+/// are drawn from
+#[derive(Debug, Clone, Copy)]
+struct Path {
+    index: u32,
+    /// Next unused dimension in [`BOUNCE_BASES`]
+    dimension: usize,
+    rotations: BounceRotations,
+}
+
+thread_local! {
+    /// Path being traced on the current thread, None when the bounces are
+    /// random
+    static PATH: Cell<Option<Path>> = const { Cell::new(None) };
+}
+
+/// Generator of sample points in `[0, 1) x [0, 1)`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Sampler {
+    /// Independent uniform random points
+    #[default]
+    Random,
+    /// Halton low-discrepancy sequence in bases 2 and 3: the points cover the
+    /// square more evenly, so estimates converge faster
+    Halton,
+}
+
+impl Sampler {
+    /// Returns the `index`-th point of the sequence
+    pub fn sample_2d(&self, index: u32) -> (f64, f64) {
+        match self {
+            Sampler::Random => (normal_random(), normal_random()),
+            Sampler::Halton => (radical_inverse(index, 2), radical_inverse(index, 3)),
+        }
+    }
+
+    /// Random shifts of the bounce dimensions, drawn once per pixel so that
+    /// the pixels don't all share the same pattern. Zero for
+    /// [`Sampler::Random`].
+    pub fn bounce_rotations(&self) -> BounceRotations {
+        match self {
+            Sampler::Random => [0.0; BOUNCE_BASES.len()],
+            Sampler::Halton => std::array::from_fn(|_| normal_random()),
+        }
+    }
+
+    /// Draws the bounces of the paths traced next on the current thread from
+    /// the `index`-th point of the sequence, until the returned guard is
+    /// dropped. Random samplers keep the bounces random.
+    pub fn begin_path(&self, index: u32, rotations: &BounceRotations) -> PathGuard {
+        let path = match self {
+            Sampler::Random => None,
+            Sampler::Halton => Some(Path {
+                index,
+                dimension: 0,
+                rotations: *rotations,
+            }),
+        };
+        PATH.with(|p| p.set(path));
+        PathGuard(())
+    }
+}
+
+/// Path started by [`Sampler::begin_path`]. Dropping it makes the bounces of
+/// the current thread random again, even if the tracing panics.
+#[must_use = "the path ends as soon as the guard is dropped"]
+pub struct PathGuard(());
+
+impl Drop for PathGuard {
+    fn drop(&mut self) {
+        PATH.with(|p| p.set(None));
+    }
+}
+
+/// Next 2D sample of a bounce: the next pair of dimensions of the current
+/// path, or two random numbers outside of a path or past its dimensions
+pub fn next_2d() -> (f64, f64) {
+    let point = PATH.with(|p| {
+        let mut path = p.get()?;
+        let d = path.dimension;
+        if d + 1 >= BOUNCE_BASES.len() {
+            return None;
+        }
+        path.dimension += 2;
+        p.set(Some(path));
+        let coordinate =
+            |d: usize| (radical_inverse(path.index, BOUNCE_BASES[d]) + path.rotations[d]).fract();
+        Some((coordinate(d), coordinate(d + 1)))
+    });
+    point.unwrap_or_else(|| (normal_random(), normal_random()))
+}
+
+/// Random unit vector for a bounce, uniform on the sphere. Outside of a
+/// low-discrepancy path it is [`Vec3::unit_random_on_sphere`].
+pub fn next_on_sphere() -> Vec3 {
+    if PATH.with(|p| p.get().is_none()) {
+        return Vec3::unit_random_on_sphere();
+    }
+    let (u, v) = next_2d();
+    let z = 1.0 - 2.0 * u;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * v;
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+/// Mirrors the digits of `index` written in `base` around the decimal point,
+/// e.g. 6 = 110 in base 2 becomes 0.011 = 0.375
+pub fn radical_inverse(mut index: u32, base: u32) -> f64 {
+    let inv_base = 1.0 / base as f64;
+    let mut inv = inv_base;
+    let mut result = 0.0;
+    while index > 0 {
+        result += (index % base) as f64 * inv;
+        index /= base;
+        inv *= inv_base;
+    }
+    result
+}
+
+#[cfg(test)]
+mod sampler_tests {
+    use super::*;
+    use crate::random;
+
+    #[test]
+    fn radical_inverse_digits() {
+        assert_eq!(radical_inverse(0, 2), 0.0);
+        assert_eq!(radical_inverse(1, 2), 0.5);
+        assert_eq!(radical_inverse(6, 2), 0.375);
+        assert!((radical_inverse(5, 3) - 7.0 / 9.0).abs() < 1e-12);
+    }
+
+    /// Mean absolute error of the estimate of the integral of x * y over the
+    /// unit square, which is 0.25
+    fn integration_error(sampler: Sampler, trials: u64) -> f64 {
+        const N: u32 = 256;
+        let mut error = 0.0;
+        for trial in 0..trials {
+            random::seed(trial);
+            let sum: f64 = (0..N)
+                .map(|i| sampler.sample_2d(i + trial as u32 * N))
+                .map(|(x, y)| x * y)
+                .sum();
+            error += (sum / N as f64 - 0.25).abs();
+        }
+        error / trials as f64
+    }
+
+    #[test]
+    fn halton_converges_faster() {
+        let random = integration_error(Sampler::Random, 20);
+        let halton = integration_error(Sampler::Halton, 20);
+        assert!(halton < random / 4.0, "{} vs {}", halton, random);
+    }
+
+    #[test]
+    fn bounce_dimensions() {
+        let rotations = [0.0; BOUNCE_BASES.len()];
+        let path = Sampler::Halton.begin_path(5, &rotations);
+        let first = next_2d();
+        assert_eq!(first, (radical_inverse(5, 5), radical_inverse(5, 7)));
+        assert_eq!(next_2d(), (radical_inverse(5, 11), radical_inverse(5, 13)));
+        for _ in 2..BOUNCE_BASES.len() / 2 {
+            next_2d();
+        }
+
+        // past the last dimension and after the path, back to random
+        random::seed(1);
+        let past = next_2d();
+        drop(path);
+        random::seed(1);
+        assert_eq!(next_2d(), past);
+
+        let path = Sampler::Random.begin_path(5, &rotations);
+        random::seed(1);
+        assert_eq!(next_2d(), past);
+        drop(path);
+
+        {
+            let _path = Sampler::Halton.begin_path(5, &rotations);
+            assert_eq!(next_2d(), first);
+            assert!((next_on_sphere().length() - 1.0).abs() < 1e-12);
+        }
+        random::seed(1);
+        assert_eq!(next_2d(), past);
+    }
+}
@@ -0,0 +1,424 @@
+//! Scene descriptions: a camera plus a list of spheres with their
+//! materials, that can be saved to and loaded from JSON with the
+//! `serde_json` feature
+
+use crate::aabb::Aabb;
+use crate::camera::Camera;
+use crate::hittable::{Hittable, HittableList};
+use crate::image::Color;
+#[cfg(feature = "serde_json")]
+use crate::image::Image;
+use crate::material::{Dielectric, DiffuseLight, Lambertian, Metal};
+use crate::math::Point3;
+#[cfg(feature = "serde_json")]
+use crate::math::Vec3;
+use crate::sphere::Sphere;
+
+#[cfg(feature = "serde_json")]
+use serde_json::{Value, json};
+use std::fmt;
+
+/// Material of a [`SceneSphere`], unlike the materials of the renderer it
+/// can be inspected and serialized
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SceneMaterial {
+    Lambertian {
+        albedo: Color,
+    },
+    /// See [`Metal::anisotropic`]
+    Metal {
+        albedo: Color,
+        fuzz_u: f64,
+        fuzz_v: f64,
+    },
+    Dielectric {
+        refraction_index: f64,
+    },
+    DiffuseLight {
+        color: Color,
+        intensity: f64,
+        two_sided: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SceneSphere {
+    pub center: Point3,
+    pub radius: f64,
+    pub material: SceneMaterial,
+}
+
+impl SceneSphere {
+    pub fn to_sphere(&self) -> Sphere {
+        let sphere = Sphere::new(self.center, self.radius);
+        match self.material {
+            SceneMaterial::Lambertian { albedo } => sphere.with_material(Lambertian::new(albedo)),
+            SceneMaterial::Metal {
+                albedo,
+                fuzz_u,
+                fuzz_v,
+            } => sphere.with_material(Metal::anisotropic(albedo, fuzz_u, fuzz_v)),
+            SceneMaterial::Dielectric { refraction_index } => {
+                sphere.with_material(Dielectric::new(refraction_index))
+            }
+            SceneMaterial::DiffuseLight {
+                color,
+                intensity,
+                two_sided,
+            } => {
+                let mut light = DiffuseLight::new(color, intensity);
+                light.two_sided = two_sided;
+                sphere.with_material(light)
+            }
+        }
+    }
+}
+
+/// Sanity report of a loaded scene, see [`Scene::summary`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SceneStats {
+    /// Count of top level objects
+    pub objects: usize,
+    pub triangles: usize,
+    pub bbox: Aabb,
+    /// Whether any object can be sampled as a light
+    pub has_lights: bool,
+}
+
+impl SceneStats {
+    pub fn of(world: &HittableList) -> Self {
+        Self {
+            objects: world.len(),
+            triangles: world.triangle_count(),
+            bbox: world.bounding_box(),
+            has_lights: !world.collect_lights().is_empty(),
+        }
+    }
+}
+
+impl fmt::Display for SceneStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} objects, {} triangles, ",
+            self.objects, self.triangles
+        )?;
+        if self.bbox.is_empty() {
+            write!(f, "empty bounds")?;
+        } else {
+            let (min, max) = (self.bbox.min(), self.bbox.max());
+            write!(
+                f,
+                "bounds ({}, {}, {}) to ({}, {}, {})",
+                min.x(),
+                min.y(),
+                min.z(),
+                max.x(),
+                max.y(),
+                max.z()
+            )?;
+        }
+        if !self.has_lights {
+            write!(f, ", no lights")?;
+        }
+        Ok(())
+    }
+}
+
+pub struct Scene {
+    pub camera: Camera,
+    pub spheres: Vec<SceneSphere>,
+}
+
+impl Scene {
+    pub fn new(camera: Camera) -> Self {
+        Self {
+            camera,
+            spheres: Vec::new(),
+        }
+    }
+
+    /// World to render, with one object per sphere
+    pub fn world(&self) -> HittableList {
+        let mut world = HittableList::new();
+        for sphere in &self.spheres {
+            world.add(sphere.to_sphere());
+        }
+        world
+    }
+
+    /// Object count, triangle count, bounds and lights of the world
+    pub fn summary(&self) -> SceneStats {
+        SceneStats::of(&self.world())
+    }
+
+    /// Serializes the placement, the projection and the sampling settings of
+    /// the camera, plus all the spheres
+    #[cfg(feature = "serde_json")]
+    pub fn to_json(&self) -> String {
+        let c = &self.camera;
+        let spheres: Vec<Value> = self.spheres.iter().map(sphere_to_json).collect();
+        json!({
+            "camera": {
+                "width": c.image.width,
+                "height": c.image.height,
+                "look_from": vec3(&c.look_from()),
+                "look_at": vec3(&c.target()),
+                "vup": vec3(&c.vup()),
+                "focal_length": c.focal_length,
+                "viewport_width": c.viewport_width,
+                "viewport_height": c.viewport_height,
+                "sample_per_pixel": c.sample_per_pixel,
+                "max_recursion_depth": c.max_recursion_depth,
+                "defocus_radius": c.defocus_radius,
+                "aperture_blades": c.aperture_blades,
+                // as a string, readers often parse every number as a double
+                "seed": c.seed.to_string(),
+            },
+            "spheres": spheres,
+        })
+        .to_string()
+    }
+
+    #[cfg(feature = "serde_json")]
+    pub fn from_json(text: &str) -> Result<Scene, String> {
+        let json: Value = serde_json::from_str(text).map_err(|e| e.to_string())?;
+
+        let c = get(&json, "camera")?;
+        let int = |key: &str| -> Result<i64, String> {
+            get(c, key)?
+                .as_i64()
+                .ok_or_else(|| format!("Field \"{}\" must be an integer", key))
+        };
+
+        let image = Image::new(int("width")? as i32, int("height")? as i32)?;
+        let mut camera = Camera::new(image);
+        camera.look_at(
+            parse_vec3(get(c, "look_from")?)?,
+            parse_vec3(get(c, "look_at")?)?,
+            parse_vec3(get(c, "vup")?)?,
+        );
+        camera.focal_length = number(c, "focal_length")?;
+        camera.viewport_width = number(c, "viewport_width")?;
+        camera.viewport_height = number(c, "viewport_height")?;
+        camera.sample_per_pixel = int("sample_per_pixel")? as i16;
+        camera.max_recursion_depth = int("max_recursion_depth")? as i16;
+        camera.defocus_radius = number(c, "defocus_radius")?;
+        camera.aperture_blades = int("aperture_blades")? as u32;
+        camera.seed = get(c, "seed")?
+            .as_str()
+            .ok_or("Field \"seed\" must be a string")?
+            .parse()
+            .map_err(|e| format!("Invalid seed: {}", e))?;
+
+        let spheres = get(&json, "spheres")?
+            .as_array()
+            .ok_or("Field \"spheres\" must be an array")?
+            .iter()
+            .map(parse_sphere)
+            .collect::<Result<_, _>>()?;
+
+        Ok(Scene { camera, spheres })
+    }
+}
+
+#[cfg(feature = "serde_json")]
+fn get<'a>(json: &'a Value, key: &str) -> Result<&'a Value, String> {
+    json.get(key)
+        .ok_or_else(|| format!("Missing field \"{}\"", key))
+}
+
+#[cfg(feature = "serde_json")]
+fn number(json: &Value, key: &str) -> Result<f64, String> {
+    get(json, key)?
+        .as_f64()
+        .ok_or_else(|| format!("Field \"{}\" must be a number", key))
+}
+
+#[cfg(feature = "serde_json")]
+fn vec3(v: &Vec3) -> Value {
+    json!(v.components().collect::<Vec<f64>>())
+}
+
+#[cfg(feature = "serde_json")]
+fn parse_vec3(json: &Value) -> Result<Vec3, String> {
+    let components: Option<Vec<f64>> = json
+        .as_array()
+        .map(|items| items.iter().filter_map(Value::as_f64).collect());
+    match components.as_deref() {
+        Some(&[x, y, z]) if json.as_array().is_some_and(|a| a.len() == 3) => Ok(Vec3::new(x, y, z)),
+        _ => Err(format!("Expected an array of 3 numbers, got {}", json)),
+    }
+}
+
+#[cfg(feature = "serde_json")]
+fn sphere_to_json(sphere: &SceneSphere) -> Value {
+    let material = match sphere.material {
+        SceneMaterial::Lambertian { albedo } => json!({
+            "type": "lambertian",
+            "albedo": vec3(&albedo),
+        }),
+        SceneMaterial::Metal {
+            albedo,
+            fuzz_u,
+            fuzz_v,
+        } => json!({
+            "type": "metal",
+            "albedo": vec3(&albedo),
+            "fuzz_u": fuzz_u,
+            "fuzz_v": fuzz_v,
+        }),
+        SceneMaterial::Dielectric { refraction_index } => json!({
+            "type": "dielectric",
+            "refraction_index": refraction_index,
+        }),
+        SceneMaterial::DiffuseLight {
+            color,
+            intensity,
+            two_sided,
+        } => json!({
+            "type": "diffuse_light",
+            "color": vec3(&color),
+            "intensity": intensity,
+            "two_sided": two_sided,
+        }),
+    };
+
+    json!({
+        "center": vec3(&sphere.center),
+        "radius": sphere.radius,
+        "material": material,
+    })
+}
+
+#[cfg(feature = "serde_json")]
+fn parse_sphere(json: &Value) -> Result<SceneSphere, String> {
+    let m = get(json, "material")?;
+    let kind = get(m, "type")?
+        .as_str()
+        .ok_or("Field \"type\" must be a string")?;
+    let material = match kind {
+        "lambertian" => SceneMaterial::Lambertian {
+            albedo: parse_vec3(get(m, "albedo")?)?,
+        },
+        "metal" => SceneMaterial::Metal {
+            albedo: parse_vec3(get(m, "albedo")?)?,
+            fuzz_u: number(m, "fuzz_u")?,
+            fuzz_v: number(m, "fuzz_v")?,
+        },
+        "dielectric" => SceneMaterial::Dielectric {
+            refraction_index: number(m, "refraction_index")?,
+        },
+        "diffuse_light" => SceneMaterial::DiffuseLight {
+            color: parse_vec3(get(m, "color")?)?,
+            intensity: number(m, "intensity")?,
+            two_sided: get(m, "two_sided")?
+                .as_bool()
+                .ok_or("Field \"two_sided\" must be a boolean")?,
+        },
+        other => return Err(format!("Unknown material \"{}\"", other)),
+    };
+
+    Ok(SceneSphere {
+        center: parse_vec3(get(json, "center")?)?,
+        radius: number(json, "radius")?,
+        material,
+    })
+}
+
+#[cfg(test)]
+mod scene_tests {
+    use super::*;
+    use crate::image::Image;
+
+    #[cfg(feature = "serde_json")]
+    #[test]
+    fn json_round_trip() {
+        let mut camera = Camera::new(Image::new(64, 36).unwrap());
+        camera.look_at(
+            Point3::new(1., 2., 3.),
+            Point3::new(0., 0., -1.),
+            Vec3::new(0., 1., 0.),
+        );
+        camera.focal_length = 1.5;
+        camera.set_viewport_from_height(2.0);
+        camera.sample_per_pixel = 7;
+        camera.seed = u64::MAX;
+
+        let mut scene = Scene::new(camera);
+        scene.spheres.push(SceneSphere {
+            center: Point3::new(0., 0., -1.),
+            radius: 0.5,
+            material: SceneMaterial::Lambertian {
+                albedo: Color::new(0.1, 0.2, 0.3),
+            },
+        });
+        scene.spheres.push(SceneSphere {
+            center: Point3::new(1., 0., -1.),
+            radius: 0.25,
+            material: SceneMaterial::Metal {
+                albedo: Color::new(0.9, 0.8, 0.7),
+                fuzz_u: 0.1,
+                fuzz_v: 0.4,
+            },
+        });
+        scene.spheres.push(SceneSphere {
+            center: Point3::new(-1., 0., -1.),
+            radius: 0.25,
+            material: SceneMaterial::Dielectric {
+                refraction_index: 1.5,
+            },
+        });
+        scene.spheres.push(SceneSphere {
+            center: Point3::new(0., 3., -1.),
+            radius: 1.0 / 3.0,
+            material: SceneMaterial::DiffuseLight {
+                color: Color::new(1., 0.9, 0.8),
+                intensity: 4.,
+                two_sided: false,
+            },
+        });
+
+        let json = scene.to_json();
+        let loaded = Scene::from_json(&json).unwrap();
+        assert_eq!(loaded.spheres, scene.spheres);
+        assert_eq!(loaded.world().len(), 4);
+        assert_eq!(loaded.camera.look_from(), scene.camera.look_from());
+        assert_eq!(loaded.camera.viewport_width, scene.camera.viewport_width);
+        assert_eq!(loaded.camera.seed, u64::MAX);
+        assert_eq!(loaded.to_json(), json);
+
+        assert!(Scene::from_json("{\"camera\": {}}").is_err());
+        let unknown = json.replace("dielectric", "plastic");
+        assert!(matches!(Scene::from_json(&unknown), Err(e) if e.contains("plastic")));
+    }
+
+    #[test]
+    fn summary() {
+        let mut scene = Scene::new(Camera::new(Image::new(8, 8).unwrap()));
+        let lambertian = SceneMaterial::Lambertian {
+            albedo: Color::new(0.5, 0.5, 0.5),
+        };
+        scene.spheres.push(SceneSphere {
+            center: Point3::new(0., 0., -1.),
+            radius: 0.5,
+            material: lambertian,
+        });
+        scene.spheres.push(SceneSphere {
+            center: Point3::new(2., 1., -3.),
+            radius: 1.,
+            material: lambertian,
+        });
+
+        let stats = scene.summary();
+        assert_eq!(stats.objects, 2);
+        assert_eq!(stats.triangles, 0);
+        assert!(!stats.has_lights);
+        assert_eq!(stats.bbox.min(), Point3::new(-0.5, -0.5, -4.));
+        assert_eq!(stats.bbox.max(), Point3::new(3., 2., -0.5));
+        assert_eq!(
+            stats.to_string(),
+            "2 objects, 0 triangles, bounds (-0.5, -0.5, -4) to (3, 2, -0.5), no lights"
+        );
+    }
+}
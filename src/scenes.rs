@@ -0,0 +1,112 @@
+//! Ready made scenes, mostly useful for benchmarks and tests
+
+use crate::camera::Camera;
+use crate::hittable::HittableList;
+use crate::image::{Color, Image};
+use crate::material::{Material, Metal, presets};
+use crate::math::{Point3, Vec3};
+use crate::sphere::Sphere;
+
+use std::sync::Arc;
+
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+
+/// Radius of the spheres scattered by [`random_spheres`]
+const SMALL_RADIUS: f64 = 0.2;
+
+/// Half size of the ground region covered by [`random_spheres`]
+const SPREAD: f64 = 11.0;
+
+/// Scatters `n` small spheres over a ground plane (a huge sphere), mostly
+/// matte with some metal and glass ones. The scene only depends on `seed`,
+/// the returned world holds `n + 1` objects.
+pub fn random_spheres(n: usize, seed: u64) -> (Camera, HittableList) {
+    let mut rng = SmallRng::seed_from_u64(seed);
+
+    let mut world = HittableList::new();
+    world.add(Sphere::from_center_radius(0., -1000., 0., 1000.));
+
+    for _ in 0..n {
+        let x = rng.random_range(-SPREAD..SPREAD);
+        let z = rng.random_range(-SPREAD..SPREAD);
+        let mut sphere = Sphere::from_center_radius(x, SMALL_RADIUS, z, SMALL_RADIUS);
+        sphere.material = random_material(&mut rng);
+        world.add(sphere);
+    }
+
+    let img = Image::from_aspect_ratio(400, 16.0 / 9.0).unwrap();
+    let mut camera = Camera::new(img);
+    camera.focal_length = 1.0;
+    // 20 degrees of vertical field of view
+    camera.set_viewport_from_height(2.0 * 10f64.to_radians().tan());
+    camera.look_at(
+        Point3::new(13., 2., 3.),
+        Point3::zero(),
+        Vec3::new(0., 1., 0.),
+    );
+
+    (camera, world)
+}
+
+/// Matte with a random color 80% of the time, a random fuzzy metal 15% of
+/// the time and glass otherwise
+fn random_material(rng: &mut SmallRng) -> Arc<dyn Material> {
+    let choice: f64 = rng.random();
+    if choice < 0.8 {
+        let mut color = || rng.random::<f64>() * rng.random::<f64>();
+        Arc::new(presets::matte(Color::new(color(), color(), color())))
+    } else if choice < 0.95 {
+        let mut channel = || rng.random_range(0.5..1.0);
+        let albedo = Color::new(channel(), channel(), channel());
+        Arc::new(Metal::new(albedo, rng.random_range(0.0..0.5)))
+    } else {
+        Arc::new(presets::glass())
+    }
+}
+
+#[cfg(test)]
+mod scenes_tests {
+    use super::*;
+    use crate::aabb::Aabb;
+    use crate::interval::Interval;
+    use crate::ray::Ray;
+
+    fn boxes(world: &HittableList) -> Vec<Aabb> {
+        world.objects().iter().map(|o| o.bounding_box()).collect()
+    }
+
+    /// Materials of the small spheres, seen from right above them
+    fn materials(world: &HittableList) -> Vec<String> {
+        world.objects()[1..]
+            .iter()
+            .map(|o| {
+                let top = o.bounding_box().center() + Vec3::new(0., 1., 0.);
+                let ray = Ray::new(top, Vec3::new(0., -1., 0.));
+                let rec = o.hit(&ray, Interval::positive()).unwrap();
+                format!("{:?}", rec.material)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn random_spheres_deterministic() {
+        let (_, world) = random_spheres(50, 7);
+        assert_eq!(world.len(), 51);
+
+        let (_, same) = random_spheres(50, 7);
+        assert_eq!(boxes(&world), boxes(&same));
+        assert_eq!(materials(&world), materials(&same));
+
+        let (_, other) = random_spheres(50, 8);
+        assert_ne!(boxes(&world), boxes(&other));
+        assert_ne!(materials(&world), materials(&other));
+
+        // all three kinds of material show up
+        let (_, many) = random_spheres(200, 7);
+        let materials = materials(&many);
+        for kind in ["Lambertian", "Metal", "Dielectric"] {
+            assert!(materials.iter().any(|m| m.starts_with(kind)), "{}", kind);
+        }
+    }
+}
@@ -1,27 +1,131 @@
+use crate::aabb::Aabb;
 use crate::hittable::{HitRecord, Hittable};
+use crate::image::Color;
 use crate::interval::Interval;
-use crate::math::Point3;
+use crate::light::{Light, SphereLight};
+use crate::material::{Lambertian, Material};
+use crate::math::{Point3, Vec3};
+use crate::random::normal_random;
+use crate::ray::Ray;
 
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+#[derive(Clone)]
 pub struct Sphere {
     pub center: Point3,
     pub radius: f64,
+    pub material: Arc<dyn Material>,
+    /// Ignores the hits on the inside of the surface, so that a ray starting
+    /// inside the sphere doesn't hit it
+    pub cull_backface: bool,
 }
 
 impl Sphere {
+    /// Creates a sphere with a gray diffuse material
     pub fn new(center: Point3, radius: f64) -> Self {
         Self {
             center,
             radius: f64::max(radius, 0.0),
+            material: Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+            cull_backface: false,
         }
     }
 
+    pub fn with_material<M: Material + 'static>(mut self, material: M) -> Self {
+        self.material = Arc::new(material);
+        self
+    }
+
+    /// Same as [`Sphere::new`] accepting anything convertible to a point,
+    /// e.g. `Sphere::at([0., 0., -1.], 0.5)`
+    pub fn at(center: impl Into<Point3>, radius: f64) -> Self {
+        Self::new(center.into(), radius)
+    }
+
     pub fn from_center_radius(x: f64, y: f64, z: f64, radius: f64) -> Self {
         Self::new(Point3::new(x, y, z), radius)
     }
-}
 
-impl Hittable for Sphere {
-    fn hit(&self, ray: &crate::ray::Ray, bounds: Interval) -> Option<HitRecord> {
+    /// Checks if `p` is inside the sphere or on its surface
+    pub fn contains(&self, p: &Point3) -> bool {
+        self.center.distance2(p) <= self.radius * self.radius
+    }
+
+    /// Cosine of the half angle of the cone from `from` enclosing the
+    /// sphere, None if the point is inside
+    fn cos_theta_max(&self, from: &Point3) -> Option<f64> {
+        let distance2 = self.center.distance2(from);
+        let radius2 = self.radius * self.radius;
+        (distance2 > radius2).then(|| (1.0 - radius2 / distance2).sqrt())
+    }
+
+    /// Solid angle subtended by the sphere seen from `from`,
+    /// `2 pi (1 - cos(theta_max))` with `theta_max` the half angle of the
+    /// enclosing cone. The whole sphere of directions, `4 pi`, from inside.
+    pub fn solid_angle(&self, from: &Point3) -> f64 {
+        match self.cos_theta_max(from) {
+            Some(cos_theta_max) => 2.0 * PI * (1.0 - cos_theta_max),
+            None => 4.0 * PI,
+        }
+    }
+
+    /// Random unit direction from `origin` toward the sphere, uniform in the
+    /// cone enclosing it, or in all the directions from inside
+    pub fn random_direction_toward(&self, origin: &Point3) -> Vec3 {
+        let Some(cos_theta_max) = self.cos_theta_max(origin) else {
+            return Vec3::unit_random_on_sphere();
+        };
+
+        let z = 1.0 + normal_random() * (cos_theta_max - 1.0);
+        let phi = 2.0 * PI * normal_random();
+        let r = (1.0 - z * z).max(0.0).sqrt();
+
+        // frame around the axis of the cone
+        let w = (self.center - *origin).normal();
+        let a = if w.x().abs() > 0.9 {
+            Vec3::new(0., 1., 0.)
+        } else {
+            Vec3::new(1., 0., 0.)
+        };
+        let v = w.cross(&a).normal();
+        let u = w.cross(&v);
+        r * phi.cos() * u + r * phi.sin() * v + z * w
+    }
+
+    /// Probability density with respect to the solid angle that
+    /// [`Sphere::random_direction_toward`] returns `direction` from `origin`
+    pub fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        let Some(cos_theta_max) = self.cos_theta_max(origin) else {
+            return 1.0 / (4.0 * PI);
+        };
+
+        let cosine = direction.normal().dot(&(self.center - *origin).normal());
+        if cosine >= cos_theta_max {
+            1.0 / self.solid_angle(origin)
+        } else {
+            0.0
+        }
+    }
+
+    /// Checks if the sphere has an emissive material
+    pub fn is_emissive(&self) -> bool {
+        self.material.is_emissive()
+    }
+
+    /// Surface coordinates of the point of the unit sphere `p`: `u` is the
+    /// angle around the y axis from -x, `v` the angle from -y, both
+    /// normalized to `[0, 1]`
+    pub fn surface_uv(p: &Point3) -> (f64, f64) {
+        let theta = (-p.y()).clamp(-1.0, 1.0).acos();
+        let phi = (-p.z()).atan2(p.x()) + PI;
+        (phi / (2.0 * PI), theta / PI)
+    }
+
+    /// Solves the ray-sphere quadratic, returning its two roots in increasing
+    /// order (equal when the ray is tangent). None if the ray misses the
+    /// sphere or if no root lies inside `bounds`.
+    pub fn intersect_t(&self, ray: &Ray, bounds: Interval) -> Option<(f64, f64)> {
         let d = *ray.direction();
         let c_q = self.center - *ray.origin(); // (C-Q)
 
@@ -35,21 +139,174 @@ impl Hittable for Sphere {
         }
 
         let sqrtd = discriminant.sqrt();
+        let near = (h - sqrtd) / a;
+        let far = (h + sqrtd) / a;
 
         // Check if root in range [t_min, t_max]
-        let mut root = (h - sqrtd) / a;
-        if !bounds.surrounds(root) {
-            root = (h + sqrtd) / a;
-            if !bounds.surrounds(root) {
-                return None;
-            }
+        if !bounds.surrounds(near) && !bounds.surrounds(far) {
+            return None;
         }
 
+        Some((near, far))
+    }
+}
+
+impl Hittable for Sphere {
+    fn hit(&self, ray: &Ray, bounds: Interval) -> Option<HitRecord> {
+        let (near, far) = self.intersect_t(ray, bounds)?;
+        let root = if bounds.surrounds(near) { near } else { far };
+
         let hit_point = ray.at(root);
         let outward_normal = (hit_point - self.center) / self.radius;
 
-        let rec = HitRecord::new(hit_point, outward_normal, root, ray);
+        let mut rec = HitRecord::new(hit_point, outward_normal, root, ray, self.material.clone());
+        if self.cull_backface && rec.front_face == Some(false) {
+            return None;
+        }
+        (rec.u, rec.v) = Sphere::surface_uv(&outward_normal);
+        // derivative of the point along u, undefined at the poles
+        rec.set_tangent(&Vec3::new(outward_normal.z(), 0., -outward_normal.x()));
 
         Some(rec)
     }
+
+    fn bounding_box(&self) -> Aabb {
+        let r = Vec3::new(self.radius, self.radius, self.radius);
+        Aabb::from_points(self.center - r, self.center + r)
+    }
+
+    fn lights(&self) -> Vec<Arc<dyn Light>> {
+        if self.is_emissive() {
+            vec![Arc::new(SphereLight::new(self.clone()))]
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod sphere_tests {
+    use super::*;
+
+    #[test]
+    fn cone_sampling() {
+        let sphere = Sphere::new(Point3::new(1., 2., -3.), 0.5);
+        let origin = Point3::new(0., 0., 1.);
+        let axis = (sphere.center - origin).normal();
+        let cos_theta_max = sphere.cos_theta_max(&origin).unwrap();
+
+        for _ in 0..1000 {
+            let d = sphere.random_direction_toward(&origin);
+            assert!((d.length() - 1.).abs() < 1e-12);
+            assert!(d.dot(&axis) >= cos_theta_max - 1e-12);
+            assert!(
+                sphere
+                    .hit(&Ray::new(origin, d), Interval::positive())
+                    .is_some()
+            );
+            assert_eq!(
+                sphere.pdf_value(&origin, &d),
+                1. / sphere.solid_angle(&origin)
+            );
+        }
+
+        assert_eq!(sphere.pdf_value(&origin, &-axis), 0.0);
+        assert_eq!(sphere.pdf_value(&sphere.center, &axis), 1. / (4. * PI));
+    }
+
+    #[test]
+    fn contains() {
+        let sphere = Sphere::new(Point3::new(1., 2., -3.), 0.5);
+        assert!(sphere.contains(&sphere.center));
+        assert!(sphere.contains(&Point3::new(1., 2.5, -3.)));
+        assert!(!sphere.contains(&Point3::new(2., 2., -3.)));
+    }
+
+    #[test]
+    fn solid_angle() {
+        let unit = Sphere::new(Point3::zero(), 1.);
+        let from = Point3::new(0., 0., 2.);
+        // sin(theta_max) = 1 / 2
+        let expected = 2. * PI * (1. - 3f64.sqrt() / 2.);
+        assert!((unit.solid_angle(&from) - expected).abs() < 1e-12);
+
+        assert_eq!(unit.solid_angle(&Point3::new(0.5, 0., 0.)), 4. * PI);
+        let far = unit.solid_angle(&Point3::new(0., 1000., 0.));
+        assert!((far - PI / 1e6).abs() < 1e-10);
+    }
+
+    #[test]
+    fn constructors() {
+        let spheres = [
+            Sphere::new(Point3::new(0., 0., -1.), 0.5),
+            Sphere::from_center_radius(0., 0., -1., 0.5),
+            Sphere::at([0., 0., -1.], 0.5),
+            Sphere::at((0., 0., -1.), 0.5),
+        ];
+        for s in &spheres {
+            assert_eq!(s.center, spheres[0].center);
+            assert_eq!(s.radius, spheres[0].radius);
+        }
+    }
+
+    #[test]
+    fn surface_coordinates() {
+        let uv = |x: f64, y: f64, z: f64| Sphere::surface_uv(&Point3::new(x, y, z));
+        assert_eq!(uv(-1., 0., 0.), (0.0, 0.5));
+        assert_eq!(uv(0., 0., 1.), (0.25, 0.5));
+        assert_eq!(uv(1., 0., 0.), (0.5, 0.5));
+        assert_eq!(uv(0., -1., 0.).1, 0.0);
+        assert_eq!(uv(0., 1., 0.).1, 1.0);
+
+        let sphere = Sphere::at([0., 0., -3.], 1.);
+        let ray = Ray::new(Point3::zero(), Vec3::new(0., 0., -1.));
+        let rec = sphere.hit(&ray, Interval::positive()).unwrap();
+        assert_eq!((rec.u, rec.v), (0.25, 0.5));
+    }
+
+    #[test]
+    fn backface_culling() {
+        let mut sphere = Sphere::at([0., 0., -5.], 2.);
+        let inside = Ray::new(Point3::new(0., 0., -5.), Vec3::new(0., 0., -1.));
+        let outside = Ray::new(Point3::zero(), Vec3::new(0., 0., -1.));
+        assert!(sphere.hit(&inside, Interval::positive()).is_some());
+
+        sphere.cull_backface = true;
+        assert!(sphere.hit(&inside, Interval::positive()).is_none());
+        assert_eq!(sphere.hit(&outside, Interval::positive()).unwrap().t, 3.0);
+    }
+
+    #[test]
+    fn roots_through_center() {
+        let sphere = Sphere::from_center_radius(0., 0., -5., 2.);
+        let ray = Ray::new(Point3::zero(), Vec3::new(0., 0., -1.));
+
+        let (near, far) = sphere.intersect_t(&ray, Interval::positive()).unwrap();
+        assert_eq!((near, far), (3.0, 7.0));
+        assert_eq!(0.5 * (near + far), 5.0);
+
+        // from inside only the far root is in front of the ray
+        let inside = Ray::new(Point3::new(0., 0., -5.), Vec3::new(0., 0., -1.));
+        assert_eq!(
+            sphere.intersect_t(&inside, Interval::positive()),
+            Some((-2.0, 2.0))
+        );
+        assert_eq!(sphere.hit(&inside, Interval::positive()).unwrap().t, 2.0);
+
+        let miss = Ray::new(Point3::new(3., 0., 0.), Vec3::new(0., 0., -1.));
+        assert!(sphere.intersect_t(&miss, Interval::positive()).is_none());
+    }
+
+    #[test]
+    fn tangent_frame() {
+        let sphere = Sphere::new(Point3::new(1., 2., -3.), 0.5);
+        let ray = Ray::new(Point3::zero(), Vec3::new(1.1, 1.8, -3.));
+        let rec = sphere.hit(&ray, Interval::positive()).unwrap();
+        rec.assert_tangent_frame();
+
+        // u grows along the tangent
+        let step = rec.point + 1e-6 * rec.tangent;
+        let (u, _) = Sphere::surface_uv(&((step - sphere.center) / sphere.radius));
+        assert!(u > rec.u);
+    }
 }
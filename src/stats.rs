@@ -0,0 +1,74 @@
+//! Ray counters used to report the throughput of a render
+
+use std::cell::Cell;
+use std::time::Duration;
+
+thread_local! {
+    /// Rays traced by the current thread since the last call to `take`
+    static COUNTS: Cell<RayCounts> = const { Cell::new(RayCounts::zero()) };
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RayCounts {
+    /// Rays shot from the camera
+    pub primary_rays: u64,
+    /// Queries of the whole scene, for any kind of ray
+    pub intersection_calls: u64,
+}
+
+impl RayCounts {
+    pub const fn zero() -> Self {
+        Self {
+            primary_rays: 0,
+            intersection_calls: 0,
+        }
+    }
+
+    pub fn add(&mut self, other: &RayCounts) {
+        self.primary_rays += other.primary_rays;
+        self.intersection_calls += other.intersection_calls;
+    }
+}
+
+/// Records a primary ray on the current thread
+pub fn count_primary_ray() {
+    COUNTS.with(|c| {
+        let mut counts = c.get();
+        counts.primary_rays += 1;
+        c.set(counts);
+    });
+}
+
+/// Records a query of the scene on the current thread
+pub fn count_intersection() {
+    COUNTS.with(|c| {
+        let mut counts = c.get();
+        counts.intersection_calls += 1;
+        c.set(counts);
+    });
+}
+
+/// Returns the counts of the current thread, resetting them
+pub fn take() -> RayCounts {
+    COUNTS.with(|c| c.replace(RayCounts::zero()))
+}
+
+/// Summary of a render
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderStats {
+    pub wall_time: Duration,
+    /// Pixels sampled by the render
+    pub pixels: usize,
+    pub counts: RayCounts,
+}
+
+impl RenderStats {
+    /// Millions of rays (of any kind) intersected with the scene per second
+    pub fn mrays_per_second(&self) -> f64 {
+        let seconds = self.wall_time.as_secs_f64();
+        if seconds <= 0.0 {
+            return 0.0;
+        }
+        self.counts.intersection_calls as f64 / seconds / 1e6
+    }
+}
@@ -0,0 +1,81 @@
+//! Textures give the color of a surface at every hit point
+
+use crate::image::Color;
+use crate::math::Point3;
+use crate::perlin::Perlin;
+
+use std::fmt::Debug;
+
+pub trait Texture: Send + Sync + Debug {
+    /// Color at the surface coordinates `(u, v)` of the world point `p`
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color;
+}
+
+/// Same color everywhere
+#[derive(Debug, Clone, Copy)]
+pub struct SolidColor {
+    pub color: Color,
+}
+
+impl SolidColor {
+    pub fn new(color: Color) -> Self {
+        Self { color }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
+        self.color
+    }
+}
+
+/// Marble veins along z: gray bands `sin(scale * z + turbulence)`, where the
+/// turbulence distorts the bands
+#[derive(Debug, Clone)]
+pub struct Marble {
+    pub noise: Perlin,
+    /// Frequency of the veins
+    pub scale: f64,
+    /// Strength of the distortion of the veins
+    pub turbulence_gain: f64,
+    /// Octaves of noise summed by the turbulence
+    pub depth: u32,
+}
+
+impl Marble {
+    pub fn new(seed: u64, scale: f64) -> Self {
+        Self {
+            noise: Perlin::new(seed),
+            scale,
+            turbulence_gain: 10.0,
+            depth: 7,
+        }
+    }
+}
+
+impl Texture for Marble {
+    fn value(&self, _u: f64, _v: f64, p: &Point3) -> Color {
+        let turbulence = self.turbulence_gain * self.noise.turbulence(p, self.depth);
+        let gray = 0.5 * (1.0 + (self.scale * p.z() + turbulence).sin());
+        Color::new(gray, gray, gray)
+    }
+}
+
+#[cfg(test)]
+mod texture_tests {
+    use super::*;
+
+    #[test]
+    fn marble_range() {
+        let marble = Marble::new(7, 4.0);
+        for i in 0..200 {
+            let t = i as f64 * 0.13;
+            let c = marble.value(0., 0., &Point3::new(t, 2.0 * t, -t));
+            assert!((0.0..=1.0).contains(&c.x()));
+            assert_eq!(c.x(), c.y());
+        }
+
+        let solid = SolidColor::new(Color::new(0.1, 0.2, 0.3));
+        assert_eq!(solid.value(0.5, 0.5, &Point3::zero()), solid.color);
+    }
+}
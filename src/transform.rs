@@ -0,0 +1,322 @@
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::light::{Light, LightSample};
+use crate::material::Material;
+use crate::math::{Point3, Vec3};
+use crate::matrix::Mat4;
+use crate::ray::Ray;
+
+use std::sync::Arc;
+
+/// Point or vector in world space. The wrapped value is reached explicitly
+/// through the field, so that it can't be mixed with a [`LocalVec`] by mistake.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct WorldVec(pub Vec3);
+
+/// Point or vector in the object space of a [`Transform`]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LocalVec(pub Vec3);
+
+/// Instance of an object placed in the world by an arbitrary affine
+/// transformation. Rays are moved to the object space, intersected with the
+/// object, and the hit is moved back to world space.
+pub struct Transform {
+    pub object: Box<dyn Hittable>,
+    pub to_world: Mat4,
+    pub to_world_inv: Mat4,
+}
+
+impl Transform {
+    pub fn new<T: Hittable + 'static>(object: T, to_world: Mat4) -> Result<Self, String> {
+        let to_world_inv = to_world
+            .inverse()
+            .ok_or("Transform matrix is not invertible")?;
+        Ok(Self {
+            object: Box::new(object),
+            to_world,
+            to_world_inv,
+        })
+    }
+
+    pub fn to_local_point(&self, p: WorldVec) -> LocalVec {
+        LocalVec(self.to_world_inv.transform_point(&p.0))
+    }
+
+    pub fn to_local_vector(&self, v: WorldVec) -> LocalVec {
+        LocalVec(self.to_world_inv.transform_vector(&v.0))
+    }
+
+    pub fn to_world_point(&self, p: LocalVec) -> WorldVec {
+        WorldVec(self.to_world.transform_point(&p.0))
+    }
+
+    pub fn to_world_vector(&self, v: LocalVec) -> WorldVec {
+        WorldVec(self.to_world.transform_vector(&v.0))
+    }
+
+    /// Normals are transformed by the inverse transpose, the result is
+    /// normalized
+    pub fn to_world_normal(&self, n: LocalVec) -> WorldVec {
+        WorldVec(
+            self.to_world_inv
+                .transpose()
+                .transform_vector(&n.0)
+                .normal(),
+        )
+    }
+}
+
+impl Hittable for Transform {
+    fn hit(&self, ray: &Ray, bounds: Interval) -> Option<HitRecord> {
+        // The direction is not normalized, so t is the same in both spaces
+        let local_ray = Ray::new(
+            self.to_local_point(WorldVec(*ray.origin())).0,
+            self.to_local_vector(WorldVec(*ray.direction())).0,
+        );
+
+        let rec = self.object.hit(&local_ray, bounds)?;
+
+        let local_outward = if rec.front_face == Some(false) {
+            -rec.normal
+        } else {
+            rec.normal
+        };
+
+        let point = self.to_world_point(LocalVec(rec.point));
+        let outward_normal = self.to_world_normal(LocalVec(local_outward));
+
+        let mut world_rec = HitRecord::new(point.0, outward_normal.0, rec.t, ray, rec.material);
+        world_rec.object_id = rec.object_id;
+        (world_rec.u, world_rec.v) = (rec.u, rec.v);
+        if rec.tangent != Vec3::zero() {
+            world_rec.set_tangent(&self.to_world_vector(LocalVec(rec.tangent)).0);
+        }
+        Some(world_rec)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let bbox = self.object.bounding_box();
+        if bbox.is_empty() {
+            return bbox;
+        }
+
+        let mut world_box = Aabb::empty();
+        for i in 0..8 {
+            let corner = Point3::new(
+                if i & 1 == 0 { bbox.x.min } else { bbox.x.max },
+                if i & 2 == 0 { bbox.y.min } else { bbox.y.max },
+                if i & 4 == 0 { bbox.z.min } else { bbox.z.max },
+            );
+            let WorldVec(p) = self.to_world_point(LocalVec(corner));
+            world_box = world_box.union(&Aabb::from_points(p, p));
+        }
+        world_box
+    }
+
+    fn lights(&self) -> Vec<Arc<dyn Light>> {
+        self.object
+            .lights()
+            .into_iter()
+            .map(|light| {
+                Arc::new(TransformedLight {
+                    light,
+                    to_world: self.to_world,
+                    to_world_inv: self.to_world_inv,
+                }) as Arc<dyn Light>
+            })
+            .collect()
+    }
+
+    fn triangle_count(&self) -> usize {
+        self.object.triangle_count()
+    }
+}
+
+/// Light of the object of a [`Transform`], sampled in the object space. The
+/// densities are converted between the solid angles of the two spaces.
+struct TransformedLight {
+    light: Arc<dyn Light>,
+    to_world: Mat4,
+    to_world_inv: Mat4,
+}
+
+impl TransformedLight {
+    /// Ratio of the solid angle around the unit vector `local` in object
+    /// space to the solid angle around its image in world space, the factor
+    /// moving a density from the object space to the world space
+    fn jacobian(&self, local: &Vec3) -> f64 {
+        let m = &self.to_world;
+        let x = m.transform_vector(&Vec3::new(1., 0., 0.));
+        let y = m.transform_vector(&Vec3::new(0., 1., 0.));
+        let z = m.transform_vector(&Vec3::new(0., 0., 1.));
+        let det = x.dot(&y.cross(&z)).abs();
+        m.transform_vector(local).length().powi(3) / det
+    }
+}
+
+impl Light for TransformedLight {
+    fn incident(&self, point: &Point3) -> Option<LightSample> {
+        let local_point = self.to_world_inv.transform_point(point);
+        let sample = self.light.incident(&local_point)?;
+
+        let to_light = self
+            .to_world
+            .transform_vector(&(sample.distance * sample.direction));
+        let distance = to_light.length();
+        if distance <= 0.0 {
+            return None;
+        }
+        let direction = to_light / distance;
+
+        if sample.pdf.is_infinite() {
+            return Some(LightSample {
+                direction,
+                distance,
+                ..sample
+            });
+        }
+        let pdf = sample.pdf * self.jacobian(&sample.direction);
+        Some(LightSample {
+            direction,
+            distance,
+            radiance: sample.radiance * (sample.pdf / pdf),
+            pdf,
+        })
+    }
+
+    fn material(&self) -> Option<&Arc<dyn Material>> {
+        self.light.material()
+    }
+
+    fn pdf(&self, point: &Point3, direction: &Vec3) -> f64 {
+        let local_point = self.to_world_inv.transform_point(point);
+        let local = self.to_world_inv.transform_vector(direction).normal();
+        let pdf = self.light.pdf(&local_point, &local);
+        if pdf == 0.0 {
+            return 0.0;
+        }
+        pdf * self.jacobian(&local)
+    }
+}
+
+#[cfg(test)]
+mod transform_tests {
+    use super::*;
+    use crate::image::Color;
+    use crate::material::DiffuseLight;
+    use crate::sphere::Sphere;
+
+    #[test]
+    fn typed_spaces() {
+        let object = Sphere::from_center_radius(0., 0., 0., 1.);
+        let moved = Transform::new(object, Mat4::translation(Vec3::new(1., 2., 3.))).unwrap();
+
+        let p: WorldVec = moved.to_world_point(LocalVec(Point3::new(0., 0., 1.)));
+        assert_eq!(p, WorldVec(Point3::new(1., 2., 4.)));
+
+        // directions are not translated
+        let v: WorldVec = moved.to_world_vector(LocalVec(Vec3::new(0., 0., 1.)));
+        assert_eq!(v, WorldVec(Vec3::new(0., 0., 1.)));
+
+        let back: LocalVec = moved.to_local_point(p);
+        assert_eq!(back, LocalVec(Point3::new(0., 0., 1.)));
+    }
+
+    #[test]
+    fn rotation_about_x() {
+        // 90 degrees about the x axis: y goes to z, z goes to -y
+        let rotation = Mat4::new([
+            [1., 0., 0., 0.],
+            [0., 0., -1., 0.],
+            [0., 1., 0., 0.],
+            [0., 0., 0., 1.],
+        ]);
+        // ellipsoid squashed along y, its top point at (0, 0.5, 0), moved
+        // to (0, 0, 0.5) by the rotation
+        let squash = Mat4::new([
+            [1., 0., 0., 0.],
+            [0., 0.5, 0., 0.],
+            [0., 0., 1., 0.],
+            [0., 0., 0., 1.],
+        ]);
+        let object = Transform::new(Sphere::from_center_radius(0., 0., 0., 1.), squash).unwrap();
+        let rotated = Transform::new(object, rotation).unwrap();
+
+        let ray = Ray::new(Point3::new(0., 0., 5.), Vec3::new(0., 0., -1.));
+        let rec = rotated.hit(&ray, Interval::positive()).unwrap();
+
+        assert!(rec.point.approx_eq(&Point3::new(0., 0., 0.5), 1e-12));
+        assert!(rec.normal.approx_eq(&Vec3::new(0., 0., 1.), 1e-12));
+        assert!((rec.t - 4.5).abs() < 1e-12);
+
+        // hit on the side of the ellipsoid, where the normal is not simply
+        // the rotated position: the point (sqrt(1/2), sqrt(1/8), 0) has normal
+        // along (1, 2, 0) before the rotation and along (1, 0, 2) after
+        let px = 0.5f64.sqrt();
+        let ray = Ray::new(Point3::new(px, 0., 5.), Vec3::new(0., 0., -1.));
+        let rec = rotated.hit(&ray, Interval::positive()).unwrap();
+        assert!((rec.point.z() - 0.125f64.sqrt()).abs() < 1e-12);
+        let expected = Vec3::new(1., 0., 2.) / 5f64.sqrt();
+        assert!((rec.normal.dot(&expected) - 1.0).abs() < 1e-9);
+
+        let bbox = rotated.bounding_box();
+        assert!((bbox.z.max - 0.5).abs() < 1e-12);
+        assert!((bbox.y.max - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn transformed_lights() {
+        let white = Color::new(1., 1., 1.);
+        let lamp = Sphere::new(Point3::zero(), 0.5).with_material(DiffuseLight::new(white, 4.));
+        let to_world =
+            Mat4::translation(Vec3::new(0., 10., 0.)) * Mat4::scale(Vec3::new(2., 2., 2.));
+        let moved = Transform::new(lamp, to_world).unwrap();
+        let lights = moved.lights();
+        assert_eq!(lights.len(), 1);
+        assert!(
+            Transform::new(Sphere::new(Point3::zero(), 1.), to_world)
+                .unwrap()
+                .lights()
+                .is_empty()
+        );
+
+        // same light as the sphere of radius 1 placed directly in the world
+        let solid_angle = Sphere::new(Point3::new(0., 10., 0.), 1.).solid_angle(&Point3::zero());
+        for _ in 0..100 {
+            let sample = lights[0].incident(&Point3::zero()).unwrap();
+            assert!(sample.distance > 8.9 && sample.distance < 10.);
+            assert!((sample.radiance.x() - 4. * solid_angle).abs() < 1e-9);
+            let pdf = lights[0].pdf(&Point3::zero(), &sample.direction);
+            assert!((sample.pdf / pdf - 1.).abs() < 1e-9);
+        }
+        assert_eq!(lights[0].pdf(&Point3::zero(), &Vec3::new(1., 0., 0.)), 0.0);
+
+        // squashed light: the sampled directions hit the emitter in world
+        // space and their densities match the ones of the directions
+        let squashed = Transform::new(
+            Sphere::new(Point3::zero(), 1.).with_material(DiffuseLight::new(white, 4.)),
+            Mat4::translation(Vec3::new(1., 5., 0.)) * Mat4::scale(Vec3::new(1., 0.25, 2.)),
+        )
+        .unwrap();
+        let light = &squashed.lights()[0];
+        for _ in 0..100 {
+            let sample = light.incident(&Point3::zero()).unwrap();
+            let ray = Ray::new(Point3::zero(), sample.direction);
+            let rec = squashed.hit(&ray, Interval::positive()).unwrap();
+            assert!((rec.t - sample.distance).abs() < 1e-9);
+            let pdf = light.pdf(&Point3::zero(), &sample.direction);
+            assert!((sample.pdf / pdf - 1.).abs() < 1e-9);
+            assert!((sample.radiance.x() * sample.pdf - 4.).abs() < 1e-9);
+        }
+
+        // the density integrates to one over the sphere of directions
+        let n = 100_000;
+        let from = Point3::new(1., 3., 0.);
+        let total: f64 = (0..n)
+            .map(|_| light.pdf(&from, &Vec3::unit_random_on_sphere()))
+            .sum();
+        let integral = 4. * std::f64::consts::PI * total / n as f64;
+        assert!((integral - 1.).abs() < 0.03, "{integral}");
+    }
+}